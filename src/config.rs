@@ -1,28 +1,202 @@
 use anyhow::{Context, Result};
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::theme::Theme;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub max_entries: usize,
     pub ignored_patterns: Vec<String>,
+    /// Skip paths with a component starting with `.` (`~/.config/fish`)
+    pub ignore_hidden: bool,
+    /// Count visits through a symlink for its target, keeping the link's name
+    pub record_symlink_aliases: bool,
+    /// Don't record the home directory, where every new shell starts
+    pub ignore_home_dir: bool,
     pub update_threshold_hours: u64,
     pub enable_fuzzy_matching: bool,
+    pub fuzzy_case_sensitive: bool,
+    /// Keywords shorter than this many characters skip fuzzy matching
+    pub min_fuzzy_query_len: usize,
+    /// Substring fallback only matches at the start of a path component or word
+    pub substring_at_word_start: bool,
+    /// Boost recently modified directories among a query's best candidates
+    pub use_mtime: bool,
+    /// Boost git repository roots (and directories close to one) among a
+    /// query's best candidates
+    pub prefer_git_root: bool,
+    /// Keywords rewritten before matching (`cfg` -> `config`)
+    pub query_aliases: HashMap<String, String>,
+    /// Characters of a query used for matching; the rest is ignored
+    pub max_query_len: usize,
     pub show_stats_on_query: bool,
     pub auto_clean_on_startup: bool,
     pub fzf_options: String,
+    /// Shell command the `x` function runs after every successful jump
+    pub post_jump_command: Option<String>,
+    /// fzf `--height` for `xneo query --pick` (e.g. `40%` or `20`)
+    pub picker_height: Option<String>,
+    /// fzf `--preview` command for `xneo query --pick`; `{}` is the directory
+    pub picker_preview_command: Option<String>,
+    /// Skip recording while any of these environment variables is set
+    pub skip_if_env_set: Vec<String>,
+    /// Top-level directories that hold your projects (`~` and `$VARS` are expanded)
+    pub search_roots: Vec<String>,
+    /// Skip paths nested more than this many levels below a search root
+    pub max_track_depth_from_root: Option<usize>,
+    /// Skip paths with fewer components than this (`/` has 0, `/tmp` has 1)
+    pub min_path_components: usize,
+    /// Stale paths removed per statement by `xneo clean`
+    pub purge_batch_size: usize,
+    /// Days of jump history kept for `xneo insights`
+    pub history_retention_days: u64,
+    /// Look in `$CDPATH` directories when nothing in the database matches
+    pub use_cdpath: bool,
+    /// Match bookmark names ignoring (ASCII) case; names are always trimmed
+    pub bookmark_case_insensitive: bool,
+    /// What `xneo` without a subcommand does
+    pub bare_invocation: BareInvocation,
+    /// Where `x` with no arguments goes instead of the home directory
+    pub default_dir: Option<String>,
+    /// Output colors: `auto`, `dark`, `light` or `none`
+    pub theme: Theme,
+    /// Queue `add` in a log file and apply it on the next query
+    pub buffer_adds: bool,
+    /// Skip the picker when the top rank is at least this many times the runner-up's
+    pub auto_select_if_dominant: Option<f64>,
 
     #[serde(skip)]
     #[serde(default = "default_globset")]
     pub(crate) compiled_ignores: GlobSet,
 }
 
+/// What `Config::reset` did
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigReset {
+    /// The confirmation was declined; nothing changed
+    Declined,
+    /// Defaults were written; `backup` is the copy of the previous file, if
+    /// there was one
+    Done { backup: Option<PathBuf> },
+}
+
+/// Behavior of `xneo` run without a subcommand
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BareInvocation {
+    /// Help in a terminal, the home directory otherwise (`cd (command xneo)`)
+    Auto,
+    /// Always print the home directory
+    Home,
+    /// Always print help and a short status
+    Help,
+}
+
+impl BareInvocation {
+    /// Whether to print the home directory given where stdout goes. The shell
+    /// `x` function captures stdout, so it always gets the home directory in
+    /// `auto` mode.
+    pub fn prints_home(self, stdout_is_terminal: bool) -> bool {
+        match self {
+            BareInvocation::Auto => !stdout_is_terminal,
+            BareInvocation::Home => true,
+            BareInvocation::Help => false,
+        }
+    }
+}
+
+/// `conf.d/*.json` next to the config file, sorted by name
+fn fragment_paths(config_path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(dir) = config_path.parent().map(|parent| parent.join("conf.d")) else {
+        return Ok(Vec::new());
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_json(path: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+    if !value.is_object() {
+        anyhow::bail!(
+            "Failed to parse config file: {:?} is not a JSON object",
+            path
+        );
+    }
+    Ok(value)
+}
+
+/// Overlays the keys of `overlay` onto `base`. With `append_arrays`, an
+/// array in both is concatenated instead of replaced.
+fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value, append_arrays: bool) {
+    let (Some(base), serde_json::Value::Object(overlay)) = (base.as_object_mut(), overlay) else {
+        return;
+    };
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(serde_json::Value::Array(existing)), serde_json::Value::Array(more))
+                if append_arrays =>
+            {
+                existing.extend(more)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 fn default_globset() -> GlobSet {
     GlobSetBuilder::new().build().unwrap()
 }
 
+/// Compiles ignore patterns against full paths.
+///
+/// `*` never crosses a `/` (only `**` does), so `/mnt/*` matches direct
+/// children only. Patterns may be relative-style (`**/target`) or anchored
+/// at an absolute prefix (`/mnt/data/**`); an anchored `dir/**` pattern also
+/// matches `dir` itself.
+fn build_ignore_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(compile_glob(pattern)?);
+
+        if let Some(prefix) = pattern.strip_suffix("/**") {
+            if prefix.starts_with('/') {
+                builder.add(compile_glob(prefix)?);
+            }
+        }
+    }
+    builder
+        .build()
+        .context("Failed to build globset from ignored patterns")
+}
+
+fn compile_glob(pattern: &str) -> Result<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob pattern in config: '{}'", pattern))
+}
+
 impl Default for Config {
     fn default() -> Self {
         let ignored_patterns = vec![
@@ -49,59 +223,71 @@ impl Default for Config {
             "**/*.tmp".to_string(),
         ];
 
-        let mut builder = GlobSetBuilder::new();
-        for pattern in &ignored_patterns {
-            if let Ok(glob) = Glob::new(pattern) {
-                builder.add(glob);
-            }
-        }
-
-        let compiled_ignores = builder
-            .build()
-            .unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap());
+        let compiled_ignores =
+            build_ignore_globset(&ignored_patterns).unwrap_or_else(|_| default_globset());
 
         Self {
             max_entries: 1000,
             ignored_patterns,
+            ignore_hidden: false,
+            record_symlink_aliases: false,
+            ignore_home_dir: true,
             update_threshold_hours: 168,
             enable_fuzzy_matching: true,
+            fuzzy_case_sensitive: false,
+            min_fuzzy_query_len: 2,
+            substring_at_word_start: true,
+            use_mtime: false,
+            prefer_git_root: false,
+            query_aliases: HashMap::new(),
+            max_query_len: 256,
             show_stats_on_query: false,
             auto_clean_on_startup: false,
             fzf_options: "--height=40% --reverse --border".to_string(),
+            post_jump_command: None,
+            picker_height: None,
+            picker_preview_command: None,
+            skip_if_env_set: Vec::new(),
+            search_roots: Vec::new(),
+            max_track_depth_from_root: None,
+            min_path_components: 0,
+            purge_batch_size: 500,
+            history_retention_days: 90,
+            use_cdpath: false,
+            bookmark_case_insensitive: false,
+            bare_invocation: BareInvocation::Auto,
+            default_dir: None,
+            theme: Theme::Auto,
+            buffer_adds: false,
+            auto_select_if_dominant: None,
             compiled_ignores,
         }
     }
 }
 
 impl Config {
+    /// Loads the config file, then merges every `conf.d/*.json` fragment next
+    /// to it in file name order: arrays are appended to, other values replaced.
     pub fn load() -> Result<Self> {
         let config_path = Self::config_path()?;
+        if !config_path.exists() {
+            Config::default().save()?;
+        }
 
-        if config_path.exists() {
-            let content = fs::read_to_string(&config_path)
-                .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
-            let mut config: Config =
-                serde_json::from_str(&content).with_context(|| "Failed to parse config file")?;
-
-            config.compile_ignores()?;
-            Ok(config)
-        } else {
-            let config = Config::default();
-            config.save()?;
-            Ok(config)
+        let mut merged = serde_json::to_value(Config::default())?;
+        merge_json(&mut merged, read_json(&config_path)?, false);
+        for fragment in fragment_paths(&config_path)? {
+            merge_json(&mut merged, read_json(&fragment)?, true);
         }
+
+        let mut config: Config =
+            serde_json::from_value(merged).with_context(|| "Failed to parse config file")?;
+        config.compile_ignores()?;
+        Ok(config)
     }
 
     fn compile_ignores(&mut self) -> Result<()> {
-        let mut builder = GlobSetBuilder::new();
-        for pattern in &self.ignored_patterns {
-            let glob = Glob::new(pattern)
-                .with_context(|| format!("Invalid glob pattern in config: '{}'", pattern))?;
-            builder.add(glob);
-        }
-        self.compiled_ignores = builder
-            .build()
-            .context("Failed to build globset from ignored patterns")?;
+        self.compiled_ignores = build_ignore_globset(&self.ignored_patterns)?;
         Ok(())
     }
 
@@ -120,13 +306,154 @@ impl Config {
         Ok(())
     }
 
-    fn config_path() -> Result<PathBuf> {
+    /// Resolves the config file location, honoring `XNEO_CONFIG_PATH` when set.
+    pub fn config_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os("XNEO_CONFIG_PATH").filter(|p| !p.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+
         let config_dir = dirs::config_dir().context("Failed to find user's config directory")?;
         Ok(config_dir.join("xneo").join("config.json"))
     }
 
+    /// Where `reset` keeps the previous config: the config path with `.bak` appended
+    pub fn backup_path() -> Result<PathBuf> {
+        let mut path = Self::config_path()?.into_os_string();
+        path.push(".bak");
+        Ok(PathBuf::from(path))
+    }
+
+    /// Overwrites the config file with defaults once `confirm` agrees, copying the
+    /// existing file to `backup_path()` first.
+    pub fn reset(confirm: impl FnOnce() -> Result<bool>) -> Result<ConfigReset> {
+        if !confirm()? {
+            return Ok(ConfigReset::Declined);
+        }
+
+        let config_path = Self::config_path()?;
+        let mut backup = None;
+        if config_path.exists() {
+            let backup_path = Self::backup_path()?;
+            fs::copy(&config_path, &backup_path)
+                .with_context(|| format!("Failed to back up config file to {:?}", backup_path))?;
+            backup = Some(backup_path);
+        }
+
+        Config::default().save()?;
+        Ok(ConfigReset::Done { backup })
+    }
+
+    /// Returns the first configured variable present in the environment
+    pub fn skip_env_var(&self) -> Option<&str> {
+        self.skip_if_env_set
+            .iter()
+            .find(|name| std::env::var_os(name.as_str()).is_some())
+            .map(String::as_str)
+    }
+
+    /// Whether `path` is nested deeper than `max_track_depth_from_root` below a search root
+    pub fn exceeds_track_depth(&self, path: &str) -> bool {
+        let Some(max_depth) = self.max_track_depth_from_root else {
+            return false;
+        };
+
+        let path = Path::new(path);
+        self.expanded_search_roots().iter().any(|root| {
+            path.strip_prefix(root)
+                .map(|rest| rest.components().count() > max_depth)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Whether `path` has fewer than `min_path_components` named components
+    pub fn is_too_shallow(&self, path: &str) -> bool {
+        // A Windows drive (`C:`) is a prefix, not a component, on every platform
+        let bytes = path.as_bytes();
+        let path = if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            &path[2..]
+        } else {
+            path
+        };
+
+        let components = Path::new(path)
+            .components()
+            .filter(|component| matches!(component, std::path::Component::Normal(_)))
+            .count();
+        components < self.min_path_components
+    }
+
+    /// Directory printed by a bare `xneo`: `default_dir` (expanded) if it
+    /// exists, the home directory otherwise
+    pub fn landing_dir(&self) -> Option<PathBuf> {
+        self.default_dir
+            .as_deref()
+            .map(|dir| PathBuf::from(crate::nav::expand_path(dir)))
+            .filter(|dir| dir.is_dir())
+            .or_else(dirs::home_dir)
+    }
+
+    /// Search roots with `~` and environment variables expanded
+    pub fn expanded_search_roots(&self) -> Vec<PathBuf> {
+        self.search_roots
+            .iter()
+            .map(|root| PathBuf::from(crate::nav::expand_path(root)))
+            .collect()
+    }
+
+    /// `keywords` with each one that is exactly a `query_aliases` key replaced
+    /// by its value
+    pub fn expand_query_aliases(&self, keywords: Vec<String>) -> Vec<String> {
+        if self.query_aliases.is_empty() {
+            return keywords;
+        }
+        keywords
+            .into_iter()
+            .map(|keyword| match self.query_aliases.get(&keyword) {
+                Some(expanded) => expanded.clone(),
+                None => keyword,
+            })
+            .collect()
+    }
+
+    /// Fields whose value differs from `Config::default()`, as
+    /// `(name, default, current)` sorted by name
+    pub fn diff_from_default(&self) -> Result<Vec<(String, serde_json::Value, serde_json::Value)>> {
+        let serde_json::Value::Object(defaults) = serde_json::to_value(Config::default())? else {
+            return Ok(Vec::new());
+        };
+        let current = serde_json::to_value(self)?;
+
+        Ok(defaults
+            .into_iter()
+            .filter_map(|(field, default)| {
+                let value = current.get(&field)?;
+                (*value != default).then(|| (field, default, value.clone()))
+            })
+            .collect())
+    }
+
+    /// Whether `ignore_home_dir` is on and `path` is the home directory
+    pub fn is_home_dir(&self, path: &str) -> bool {
+        self.ignore_home_dir
+            && dirs::home_dir().is_some_and(|home| {
+                crate::nav::normalize_path(&home.to_string_lossy())
+                    == crate::nav::normalize_path(path)
+            })
+    }
+
+    /// Whether `ignore_hidden` is on and `path` has a dotfile component
+    pub fn is_hidden(&self, path: &str) -> bool {
+        self.ignore_hidden
+            && crate::nav::normalize_separators(path)
+                .split('/')
+                .any(|component| {
+                    component.starts_with('.') && component != "." && component != ".."
+                })
+    }
+
+    /// Globs are matched against the `/`-separated form of `path`
     pub fn is_ignored(&self, path: &str) -> bool {
-        self.compiled_ignores.is_match(Path::new(path))
+        let path = crate::nav::normalize_separators(path);
+        self.compiled_ignores.is_match(Path::new(&path))
     }
 }
-
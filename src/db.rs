@@ -1,14 +1,21 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use rusqlite::{params, Connection};
-use std::collections::HashSet;
-use std::path::Path;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
+use crate::nav;
+use crate::project::{self, ProjectType};
 
-#[derive(Debug, Clone)]
+/// A tracked directory; serializes (for `query --json`) with
+/// `last_access` as RFC 3339
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct DirEntry {
     pub path: String,
     pub last_access: DateTime<Utc>,
@@ -16,10 +23,77 @@ pub struct DirEntry {
     pub rank: f64,
 }
 
+/// Columns of a `dirs` row in the order `map_dir_row` reads them
+pub(crate) const DIR_COLUMNS: &str = "path, last_access, visits_total, rank";
+
+/// Maps a row selected as `SELECT {DIR_COLUMNS} ...`. The rank is the
+/// persisted one; callers needing the exact frecency recompute it.
+pub(crate) fn map_dir_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DirEntry> {
+    Ok(DirEntry {
+        path: row.get(0)?,
+        last_access: row.get(1)?,
+        visits: row.get(2)?,
+        rank: row.get(3)?,
+    })
+}
+
 #[derive(Debug)]
 pub struct Bookmark {
     pub name: String,
     pub path: String,
+    pub aliases: Vec<String>,
+    /// Jumps through this bookmark or its aliases (see `resolve_bookmark`)
+    pub use_count: u32,
+    pub last_used: Option<DateTime<Utc>>,
+    /// Place in `bookmark list` set with `bookmark reorder`; unordered
+    /// bookmarks follow the ordered ones, by name
+    pub position: Option<u32>,
+}
+
+impl Bookmark {
+    /// Whether the (expanded) target currently exists. Bookmarks to unmounted
+    /// drives or network shares can be stored anyway with `--allow-missing`.
+    pub fn is_verified(&self) -> bool {
+        Path::new(&nav::expand_path(&self.path)).exists()
+    }
+}
+
+/// Problems found by `xneo bookmark check`, each list sorted
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BookmarkCheck {
+    /// Bookmarks with an empty or whitespace-only name or path, which can't
+    /// be jumped to or removed by name; `fix_bookmarks` deletes them
+    pub invalid: Vec<String>,
+    /// Names of bookmarks whose target doesn't exist (possibly on purpose,
+    /// see `--allow-missing`), only reported
+    pub missing: Vec<String>,
+    /// Targets (expanded) shared by several bookmarks, with their names
+    pub duplicates: Vec<(String, Vec<String>)>,
+}
+
+impl BookmarkCheck {
+    pub fn is_clean(&self) -> bool {
+        self.invalid.is_empty() && self.missing.is_empty() && self.duplicates.is_empty()
+    }
+}
+
+/// Tracked directories sharing a project root, for `query --group-by-root`
+#[derive(Debug, Clone)]
+pub struct RootGroup {
+    /// The outermost tracked project directory (one with a detected
+    /// project type) holding the members, or the lone member's own path
+    /// when it isn't inside any
+    pub root: String,
+    /// The grouped entries, in the order they were given, root included
+    /// when it was among them
+    pub members: Vec<DirEntry>,
+}
+
+/// A single recorded jump into a directory
+#[derive(Debug, Clone)]
+pub struct HistoryEvent {
+    pub path: String,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug)]
@@ -30,36 +104,488 @@ pub struct Stats {
     pub recently_visited: Vec<DirEntry>,
 }
 
+/// Outcome of a `clean` run, used for the `--porcelain` output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanSummary {
+    pub stale: usize,
+    pub removed: usize,
+    /// Rows merged into another spelling of the same path, see
+    /// `Database::merge_case_duplicates`
+    pub merged: usize,
+}
+
+impl CleanSummary {
+    /// Exit code: nothing to clean
+    pub const EXIT_CLEAN: i32 = 0;
+    /// Exit code: stale entries were removed
+    pub const EXIT_CLEANED: i32 = 10;
+    /// Exit code: stale entries were found but left in place
+    pub const EXIT_STALE: i32 = 11;
+
+    pub fn porcelain(&self) -> String {
+        format!(
+            "stale {}\nremoved {}\nmerged {}",
+            self.stale, self.removed, self.merged
+        )
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.removed > 0 || self.merged > 0 {
+            Self::EXIT_CLEANED
+        } else if self.stale > 0 {
+            Self::EXIT_STALE
+        } else {
+            Self::EXIT_CLEAN
+        }
+    }
+}
+
+/// On-disk size of the database, printed by `xneo clean --report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageReport {
+    pub page_size: u64,
+    pub page_count: u64,
+    /// Pages left unused by deletions, which only `VACUUM` gives back
+    pub free_pages: u64,
+    pub dir_rows: u64,
+    pub event_rows: u64,
+    pub bookmark_rows: u64,
+}
+
+impl StorageReport {
+    /// Size of the main database file (the WAL, if any, is not counted)
+    pub fn file_bytes(&self) -> u64 {
+        self.page_size * self.page_count
+    }
+
+    pub fn total_rows(&self) -> u64 {
+        self.dir_rows + self.event_rows + self.bookmark_rows
+    }
+
+    /// File bytes per stored row, including indexes and overhead
+    pub fn bytes_per_row(&self) -> Option<u64> {
+        self.file_bytes().checked_div(self.total_rows())
+    }
+
+    /// What a vacuum would shrink the file by
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.page_size * self.free_pages
+    }
+}
+
+/// How `Database::explain` resolved a query, printed by `xneo query --explain`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct QueryTrace {
+    pub keywords: Vec<String>,
+    /// Bookmark named by a single keyword. `xneo query` jumps there instead
+    /// of to the directory matches below, however frecent they are.
+    pub bookmark: Option<String>,
+    /// Set when `xneo query` answers without the directory matches below:
+    /// `dots`, `bookmark` or `bookmark_prefix` (see `Shortcut`)
+    pub shortcut: Option<&'static str>,
+    /// Bookmarked directory the query was scoped to (`work api`)
+    pub scope: Option<String>,
+    /// Stage that produced the results, `None` if nothing matched
+    pub stage: Option<&'static str>,
+    /// Candidates found by each stage tried, in order
+    pub stages: Vec<StageTrace>,
+    /// Best results with the score they were ordered by
+    pub top: Vec<ScoredPath>,
+    /// Whether the `timeout` cut the scan short
+    pub timed_out: bool,
+}
+
+/// How `xneo query` answers a single keyword before ranking directories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shortcut {
+    /// `..`, `...`: up from the current directory (`nav::resolve_dots`)
+    Dots,
+    /// An exact bookmark or alias name: the canonical name and stored path
+    Bookmark { name: String, path: String },
+    /// Paths of the bookmarks and aliases whose name starts with the keyword
+    BookmarkPrefix(Vec<String>),
+}
+
+impl Shortcut {
+    /// Name reported in `QueryTrace::shortcut`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Shortcut::Dots => "dots",
+            Shortcut::Bookmark { .. } => "bookmark",
+            Shortcut::BookmarkPrefix(_) => "bookmark_prefix",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StageTrace {
+    pub stage: &'static str,
+    pub candidates: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ScoredPath {
+    pub path: String,
+    pub score: f64,
+}
+
+/// Candidates per matching stage whose mtime `use_mtime` (and git root
+/// `prefer_git_root`) looks at
+pub const MTIME_TOP_K: usize = 20;
+
+/// Rank multiplier for a directory modified just now with `use_mtime`; it
+/// halves with each day since (`1 + MTIME_WEIGHT / (days + 1)`)
+const MTIME_WEIGHT: f64 = 1.0;
+
+/// Rank multiplier for a git repository root with `prefer_git_root`; it
+/// shrinks with each level below the root (`1 + GIT_ROOT_WEIGHT / (depth + 1)`)
+const GIT_ROOT_WEIGHT: f64 = 1.0;
+
+/// Candidates scanned between two checks of a query's deadline
+const DEADLINE_CHECK_INTERVAL: usize = 64;
+
+/// Number of results listed in `QueryTrace::top`
+const TRACE_TOP: usize = 5;
+
+impl QueryTrace {
+    fn stage(&mut self, stage: &'static str, candidates: usize) {
+        self.stages.push(StageTrace { stage, candidates });
+    }
+
+    /// Records `stage` as the winner with its best `scored` results
+    fn resolve(&mut self, stage: &'static str, scored: impl Iterator<Item = (String, f64)>) {
+        self.stage = Some(stage);
+        self.top = scored
+            .take(TRACE_TOP)
+            .map(|(path, score)| ScoredPath { path, score })
+            .collect();
+    }
+}
+
+/// The parts of a directory's frecency rank, for `xneo rank-test`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankBreakdown {
+    pub path: String,
+    /// 0 for untracked paths, which rank 0 overall
+    pub visits: u32,
+    pub last_access: Option<DateTime<Utc>>,
+    /// Weighted frequency part of `total`
+    pub frequency: f64,
+    /// Weighted recency part of `total`
+    pub recency: f64,
+    pub total: f64,
+}
+
+/// Rows rewritten by `Database::move_prefix`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MoveSummary {
+    pub dirs: usize,
+    pub bookmarks: usize,
+}
+
+/// Outcome of the checks `Database::add` runs before recording a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddDecision {
+    Record,
+    /// A `skip_if_env_set` variable is present
+    SkipEnv(String),
+    /// The path matches an `ignored_patterns` entry
+    Ignored,
+    /// `ignore_hidden` is on and the path has a dotfile component
+    Hidden,
+    /// `ignore_home_dir` is on and the path is the home directory
+    HomeDir,
+    /// The path is deeper than `max_track_depth_from_root` below a search root
+    TooDeep,
+    /// The path has fewer than `min_path_components` components
+    TooShallow,
+    /// The database was written by a newer xneo and is read-only
+    NewerSchema,
+}
+
+impl std::fmt::Display for AddDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddDecision::Record => write!(f, "record"),
+            AddDecision::SkipEnv(var) => write!(f, "skip: environment variable {} is set", var),
+            AddDecision::Ignored => write!(f, "skip: matches an ignored pattern"),
+            AddDecision::Hidden => write!(f, "skip: inside a hidden directory"),
+            AddDecision::HomeDir => write!(f, "skip: home directory"),
+            AddDecision::TooDeep => write!(f, "skip: nested too deep below a search root"),
+            AddDecision::TooShallow => write!(f, "skip: fewer path components than configured"),
+            AddDecision::NewerSchema => write!(f, "skip: database is from a newer xneo"),
+        }
+    }
+}
+
+/// Per-invocation options for `Database::query_with`
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Boost candidates that share a longer path prefix with this directory
+    pub prefer_cwd: Option<PathBuf>,
+    /// Override the recency weight (0.0 to 1.0) used by the frecency rank
+    pub age_weight: Option<f64>,
+    /// Number of ranked results to skip
+    pub offset: usize,
+    /// Maximum number of results (defaults to `DEFAULT_QUERY_LIMIT`)
+    pub limit: Option<usize>,
+    /// Match paths containing any keyword instead of the whole query
+    pub any: bool,
+    /// Return only the top result when its rank is at least this multiple of
+    /// the second one's (see `is_dominant`)
+    pub auto_select_if_dominant: Option<f64>,
+    /// Drop ranked results that don't meet these thresholds
+    pub filter: QueryFilter,
+    /// Stop scanning candidates (best-ranked first) after this long and
+    /// return the best results found so far
+    pub timeout: Option<Duration>,
+    /// Leave this directory out of the results, so the next best match wins
+    /// when the best one is where the user already is
+    pub skip_path: Option<PathBuf>,
+}
+
+/// Thresholds a result must meet to be returned, applied after ranking
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryFilter {
+    /// Visited at least this many times
+    pub min_visits: Option<u32>,
+    /// Last visited no longer ago than this
+    pub max_age: Option<chrono::Duration>,
+    /// Detected as this kind of project when last visited
+    pub project_type: Option<ProjectType>,
+}
+
+impl QueryFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_visits.is_none() && self.max_age.is_none() && self.project_type.is_none()
+    }
+
+    /// Whether `entry` meets the visit and age thresholds; the project type
+    /// is stored separately, see `Database::retain_filtered`
+    pub fn keeps(&self, entry: &DirEntry, now: &DateTime<Utc>) -> bool {
+        self.min_visits.is_none_or(|min| entry.visits >= min)
+            && self
+                .max_age
+                .is_none_or(|age| *now - entry.last_access <= age)
+    }
+}
+
+/// Persisted ranks are approximate: a row's rank is exact right after it is
+/// visited, but its recency component decays afterwards. All ranks are
+/// recomputed when the database is opened more than this many hours after
+/// the last refresh, or on demand with `xneo rerank`.
+pub const RANK_REFRESH_INTERVAL_HOURS: i64 = 24;
+
+/// `auto_clean_on_startup` scans at most this often, across all processes
+pub const AUTO_CLEAN_INTERVAL_MINUTES: i64 = 60;
+
+/// Schema version stamped into `PRAGMA user_version`; bump it with every
+/// change to the tables in `Database::migrate`
+pub const SCHEMA_VERSION: u32 = 5;
+
+/// Upper bound for `purge_batch_size`, below SQLite's limit on bound parameters
+pub const MAX_PURGE_BATCH_SIZE: usize = 999;
+
+/// How long a handle reuses the ranked results of an identical query
+pub const QUERY_CACHE_TTL: Duration = Duration::from_secs(2);
+
+/// Distinct queries a handle remembers at once
+const QUERY_CACHE_CAPACITY: usize = 32;
+
+/// Recently ranked queries, least recently used first. Long-lived handles
+/// (`--stdin-query`, pickers redrawing) repeat queries; one-shot runs never hit.
+#[derive(Default)]
+struct QueryCache {
+    entries: Vec<CachedQuery>,
+}
+
+struct CachedQuery {
+    key: String,
+    /// `Database::write_stamp` when ranked; any write since makes it stale
+    stamp: (u64, i64),
+    ranked_at: Instant,
+    results: Vec<DirEntry>,
+}
+
+impl QueryCache {
+    fn get(&mut self, key: &str, stamp: (u64, i64)) -> Option<Vec<DirEntry>> {
+        self.entries
+            .retain(|cached| cached.stamp == stamp && cached.ranked_at.elapsed() < QUERY_CACHE_TTL);
+        let index = self.entries.iter().position(|cached| cached.key == key)?;
+        let cached = self.entries.remove(index);
+        let results = cached.results.clone();
+        self.entries.push(cached);
+        Some(results)
+    }
+
+    fn insert(&mut self, key: String, stamp: (u64, i64), results: Vec<DirEntry>) {
+        if self.entries.len() >= QUERY_CACHE_CAPACITY {
+            self.entries.remove(0);
+        }
+        self.entries.push(CachedQuery {
+            key,
+            stamp,
+            ranked_at: Instant::now(),
+            results,
+        });
+    }
+}
+
+/// Rows in each `xneo stats` list unless `--top` is given
+pub const DEFAULT_STATS_TOP: usize = 10;
+
+/// Number of results returned by a query unless a limit is given
+pub const DEFAULT_QUERY_LIMIT: usize = 20;
+
+/// Weight of the recency score in the frecency rank; frequency gets the rest
+pub const DEFAULT_AGE_WEIGHT: f64 = 0.3;
+
+/// Fuzzy score multiplier for a directory whose name contains the whole
+/// keyword with exactly the same case, anywhere in it: `Config` boosts
+/// `/a/MyConfigBackup` but not `/a/Core/myconfig` (matched through `Core`).
+/// A name equal to the keyword is already an exact-stage match.
+const EXACT_CASE_BASENAME_BONUS: f64 = 1.5;
+
+/// A handle to the xneo database.
+///
+/// Cloning is cheap: clones share the same underlying connection, so one
+/// database can be used from several threads (e.g. a reader and a writer).
+#[derive(Clone)]
 pub struct Database {
-    conn: Connection,
+    conn: Arc<Mutex<Connection>>,
     config: Config,
+    matcher: Arc<SkimMatcherV2>,
+    /// Stored schema version when it is newer than `SCHEMA_VERSION`
+    newer_schema: Option<u32>,
+    /// Append-only log of visits queued by `buffer_adds`
+    pending_log: PathBuf,
+    cache: Arc<Mutex<QueryCache>>,
+    /// Full scans run by queries (cache misses)
+    scans: Arc<AtomicUsize>,
 }
 
 impl Database {
     pub fn new(config: Config) -> Result<Self> {
-        let db_path = dirs::data_dir()
+        Self::open(&Self::db_path()?, config)
+    }
+
+    /// Resolves the database file location, honoring `XNEO_DB_PATH` when set.
+    pub fn db_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os("XNEO_DB_PATH").filter(|p| !p.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+
+        Ok(dirs::data_dir()
             .context("Failed to find user's data directory")?
-            .join("xneo/db.sqlite");
+            .join("xneo/db.sqlite"))
+    }
+
+    /// Queues a visit for the database at `db_path` without opening it.
+    ///
+    /// This only appends a line to a log file next to the database, so the
+    /// shell hook never waits for a lock; the next query or open applies it.
+    pub fn buffer_visit(db_path: &Path, path: &str, at: DateTime<Utc>) -> Result<()> {
+        if let Some(parent_dir) = db_path.parent() {
+            std::fs::create_dir_all(parent_dir).with_context(|| {
+                format!("Failed to create database directory at {:?}", parent_dir)
+            })?;
+        }
+        append_pending(&pending_log_path(db_path), &resolve_add_path(path)?, at)
+    }
 
+    pub fn open(db_path: &Path, config: Config) -> Result<Self> {
         if let Some(parent_dir) = db_path.parent() {
             std::fs::create_dir_all(parent_dir).with_context(|| {
                 format!("Failed to create database directory at {:?}", parent_dir)
             })?;
         }
 
-        let conn = Connection::open(&db_path)
+        let conn = Connection::open(db_path)
             .with_context(|| format!("Failed to open or create database at {:?}", db_path))?;
 
+        // A newer xneo may have changed the schema in ways this one can't
+        // maintain; read from it but never write
+        let stored_version: u32 =
+            conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let newer_schema = (stored_version > SCHEMA_VERSION).then_some(stored_version);
+        if newer_schema.is_none() {
+            Self::migrate(&conn)?;
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+
+        // Smart case by default; case-sensitive when configured
+        let matcher = if config.fuzzy_case_sensitive {
+            SkimMatcherV2::default().respect_case()
+        } else {
+            SkimMatcherV2::default()
+        };
+
+        let mut db = Database {
+            conn: Arc::new(Mutex::new(conn)),
+            config,
+            matcher: Arc::new(matcher),
+            newer_schema,
+            pending_log: pending_log_path(db_path),
+            cache: Arc::default(),
+            scans: Arc::default(),
+        };
+
+        if db.newer_schema.is_some() {
+            return Ok(db);
+        }
+
+        db.flush_pending()?;
+
+        // Persisted ranks drift as recency decays; refresh them periodically
+        if db.ranks_need_refresh()? {
+            db.refresh_ranks()?;
+        }
+
+        // Auto-clean stale entries on startup
+        if db.config.auto_clean_on_startup {
+            let _ = db.auto_clean(&auto_clean_lock_path(db_path));
+        }
+
+        Ok(db)
+    }
+
+    /// Creates missing tables and indices and upgrades older layouts
+    fn migrate(conn: &Connection) -> Result<()> {
         // Create dirs table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS dirs (
                 path        TEXT PRIMARY KEY,
                 last_access INTEGER NOT NULL,
-                visits_total INTEGER NOT NULL
+                visits_total INTEGER NOT NULL,
+                rank        REAL NOT NULL DEFAULT 0,
+                project_type TEXT
             )",
             [],
         )?;
 
+        // Migrate databases created before the persisted rank column
+        let has_rank: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('dirs') WHERE name = 'rank')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_rank {
+            conn.execute(
+                "ALTER TABLE dirs ADD COLUMN rank REAL NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+
+        // Migrate databases created before project detection (schema version 4)
+        let has_project_type: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('dirs') WHERE name = 'project_type')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_project_type {
+            conn.execute("ALTER TABLE dirs ADD COLUMN project_type TEXT", [])?;
+        }
+
         // Create bookmarks table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS bookmarks (
@@ -69,6 +595,78 @@ impl Database {
             [],
         )?;
 
+        // Migrate bookmarks created before usage tracking (schema version 2)
+        let has_use_count: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('bookmarks') WHERE name = 'use_count')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_use_count {
+            conn.execute(
+                "ALTER TABLE bookmarks ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+            conn.execute("ALTER TABLE bookmarks ADD COLUMN last_used TEXT", [])?;
+        }
+
+        // Migrate bookmarks created before manual ordering (schema version 5)
+        let has_position: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('bookmarks') WHERE name = 'position')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_position {
+            conn.execute("ALTER TABLE bookmarks ADD COLUMN position INTEGER", [])?;
+        }
+
+        // Symlinks visited with `record_symlink_aliases` (link -> target path),
+        // since schema version 3
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS path_aliases (
+                alias       TEXT PRIMARY KEY,
+                target      TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create bookmark aliases table (alias -> canonical bookmark name)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS bookmark_aliases (
+                alias       TEXT PRIMARY KEY,
+                target      TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Rows removed by the most recent purge, kept for `xneo undo`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS undo_dirs (
+                path        TEXT PRIMARY KEY,
+                last_access INTEGER NOT NULL,
+                visits_total INTEGER NOT NULL,
+                rank        REAL NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Create key/value table for bookkeeping (e.g. last rank refresh)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key         TEXT PRIMARY KEY,
+                value       TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create history event log (one row per recorded jump)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                path        TEXT NOT NULL,
+                timestamp   INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
         // Create indices to improve query performance
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_dirs_visits ON dirs(visits_total DESC)",
@@ -80,30 +678,108 @@ impl Database {
             [],
         )?;
 
-        let mut db = Database { conn, config };
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_dirs_rank ON dirs(rank DESC)",
+            [],
+        )?;
 
-        // Auto-clean stale entries on startup
-        if db.config.auto_clean_on_startup {
-            let _ = db.auto_clean();
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_events_timestamp ON events(timestamp)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// A warning to show when the database was written by a newer xneo.
+    /// Such a database is only read: every write is refused.
+    pub fn schema_warning(&self) -> Option<String> {
+        self.newer_schema.map(|version| {
+            format!(
+                "database schema version {} is newer than this xneo supports ({}); \
+                 opened read-only, please upgrade xneo",
+                version, SCHEMA_VERSION
+            )
+        })
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        if let Some(warning) = self.schema_warning() {
+            anyhow::bail!("Refusing to modify the database: {}", warning);
         }
+        Ok(())
+    }
 
-        Ok(db)
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic while holding the lock leaves the connection itself usable
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub fn add(&mut self, path: &str) -> Result<()> {
+    /// Decides whether `add` would record `path`, without writing anything
+    pub fn add_decision(&self, path: &str) -> AddDecision {
+        let path = &nav::normalize_path(path);
+
+        if self.newer_schema.is_some() {
+            return AddDecision::NewerSchema;
+        }
+
+        // Skip transient shells (nix-shell, git rebase editors, ...)
+        if let Some(var) = self.config.skip_env_var() {
+            return AddDecision::SkipEnv(var.to_string());
+        }
+
+        // Every shell starts at home; recording it would drown out real visits
+        if self.config.is_home_dir(path) {
+            return AddDecision::HomeDir;
+        }
+
         // Check if this path should be ignored
         if self.config.is_ignored(path) {
+            return AddDecision::Ignored;
+        }
+
+        // Dotfile directories, without going through the glob set
+        if self.config.is_hidden(path) {
+            return AddDecision::Hidden;
+        }
+
+        // Skip `/`, `/tmp` and similar noise
+        if self.config.is_too_shallow(path) {
+            return AddDecision::TooShallow;
+        }
+
+        // Skip deeply nested build/intermediate directories under a root
+        if self.config.exceeds_track_depth(path) {
+            return AddDecision::TooDeep;
+        }
+
+        AddDecision::Record
+    }
+
+    pub fn add(&mut self, path: &str) -> Result<()> {
+        self.add_at(path, Utc::now())
+    }
+
+    /// Records a visit to `path` as if it happened at `at`, for replaying
+    /// imported shell histories. An older `at` never moves `last_access` back,
+    /// and a future one is rejected.
+    /// A relative `path` is resolved against the current directory first.
+    pub fn add_at(&mut self, path: &str, at: DateTime<Utc>) -> Result<()> {
+        if at > Utc::now() {
+            anyhow::bail!("Visit time {} is in the future", at.to_rfc3339());
+        }
+        let path = &resolve_add_path(path)?;
+        let decision = self.add_decision(path);
+        if decision != AddDecision::Record {
+            log::debug!("not recording {}: {}", path, decision);
             return Ok(());
         }
 
-        let now = Utc::now();
-        self.conn.execute(
-            "INSERT INTO dirs (path, last_access, visits_total) VALUES (?1, ?2, 1)
-             ON CONFLICT(path) DO UPDATE SET
-                last_access = excluded.last_access,
-                visits_total = visits_total + 1",
-            params![path, now],
-        )?;
+        if self.config.buffer_adds {
+            return append_pending(&self.pending_log, path, at);
+        }
+
+        self.record_visit(&self.conn(), path, at)?;
+        self.prune_events(Utc::now())?;
 
         // If the number of entries exceeds the limit, delete the oldest entries
         self.maintain_size_limit()?;
@@ -111,34 +787,563 @@ impl Database {
         Ok(())
     }
 
+    /// Applies the visits queued by `buffer_adds` in one transaction and
+    /// returns how many were recorded.
+    pub fn flush_pending(&self) -> Result<usize> {
+        if self.newer_schema.is_some() {
+            return Ok(0);
+        }
+
+        // One flush at a time, or two processes could apply the same claimed
+        // log; a busy lock means the other one is applying these visits
+        let lock = flush_lock_path(&self.pending_log);
+        let lock_file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock)
+            .with_context(|| format!("Failed to open {:?}", lock))?;
+        match lock_file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(0),
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        // Claim the log so hooks running meanwhile start a fresh one. A claimed
+        // log left behind by an interrupted flush is applied first.
+        let claimed = self.pending_log.with_extension("flushing");
+        if !claimed.exists() {
+            match std::fs::rename(&self.pending_log, &claimed) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to claim pending visits at {:?}", self.pending_log)
+                    })
+                }
+            }
+        }
+
+        let contents = std::fs::read_to_string(&claimed)
+            .with_context(|| format!("Failed to read pending visits at {:?}", claimed))?;
+        let visits: Vec<(DateTime<Utc>, &str)> = contents
+            .lines()
+            .filter_map(parse_pending)
+            .filter(|(_, path)| self.add_decision(path) == AddDecision::Record)
+            .collect();
+
+        {
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            for (at, path) in &visits {
+                self.record_visit(&tx, path, *at)?;
+            }
+            tx.commit()?;
+        }
+        match std::fs::remove_file(&claimed) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {:?}", claimed))
+            }
+            _ => {}
+        }
+        drop(lock_file);
+
+        if let Some((latest, _)) = visits.last() {
+            self.prune_events(*latest)?;
+            self.maintain_size_limit()?;
+        }
+
+        Ok(visits.len())
+    }
+
+    /// Upserts a visit to `path` at `at` and logs it as a history event. With
+    /// `record_symlink_aliases`, a visit through a symlink counts for its
+    /// target and the link is kept as an alias of it.
+    fn record_visit(&self, conn: &Connection, path: &str, at: DateTime<Utc>) -> Result<()> {
+        let target = self.symlink_target(path);
+        if let Some(target) = &target {
+            conn.execute(
+                "INSERT OR REPLACE INTO path_aliases (alias, target) VALUES (?1, ?2)",
+                params![path, target],
+            )?;
+        }
+        let path = target.as_deref().unwrap_or(path);
+
+        // Markers can appear or go away, so detect again on every visit
+        let project_type = project::detect(Path::new(path));
+        let (visits, last_access): (u32, DateTime<Utc>) = conn.query_row(
+            "INSERT INTO dirs (path, last_access, visits_total, project_type) VALUES (?1, ?2, 1, ?3)
+             ON CONFLICT(path) DO UPDATE SET
+                last_access = MAX(last_access, excluded.last_access),
+                visits_total = visits_total + 1,
+                project_type = excluded.project_type
+             RETURNING visits_total, last_access",
+            params![path, at, project_type],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        // Keep the persisted rank of the visited row exact
+        let now = Utc::now().max(last_access);
+        let rank = self.calculate_rank(visits, &last_access, &now, DEFAULT_AGE_WEIGHT);
+        conn.execute(
+            "UPDATE dirs SET rank = ?1 WHERE path = ?2",
+            params![rank, path],
+        )?;
+
+        conn.execute(
+            "INSERT INTO events (path, timestamp) VALUES (?1, ?2)",
+            params![path, at],
+        )?;
+
+        Ok(())
+    }
+
+    /// The resolved target of `path` if `record_symlink_aliases` is on and
+    /// `path` goes through a symlink
+    fn symlink_target(&self, path: &str) -> Option<String> {
+        if !self.config.record_symlink_aliases {
+            return None;
+        }
+        let target = std::fs::canonicalize(path).ok()?;
+        let target = nav::normalize_path(&target.to_string_lossy());
+        (target != path).then_some(target)
+    }
+
     pub fn query(&self, keywords: &[String]) -> Result<Vec<DirEntry>> {
+        self.query_with(keywords, &QueryOptions::default())
+    }
+
+    pub fn query_with(&self, keywords: &[String], options: &QueryOptions) -> Result<Vec<DirEntry>> {
+        let keywords = self.prepare_keywords(keywords);
         if keywords.is_empty() {
             return Ok(Vec::new());
         }
 
-        // Get all entries
-        let mut stmt = self.conn.prepare(
-            "SELECT path, last_access, visits_total FROM dirs ORDER BY visits_total DESC",
+        self.flush_pending()?;
+        let limit = options.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+
+        let mut matches = self.cached_ranked_matches(&keywords, options)?;
+        // Again for results from outside the tracked rows ($CDPATH)
+        self.retain_filtered(&mut matches, &options.filter)?;
+        if let Some(skip) = &options.skip_path {
+            let skip = nav::normalize_path(&skip.to_string_lossy());
+            matches.retain(|entry| entry.path != skip);
+        }
+        if let Some(ratio) = options.auto_select_if_dominant {
+            if options.offset == 0 && is_dominant(&matches, ratio) {
+                matches.truncate(1);
+            }
+        }
+
+        // Paginate after ranking; an offset past the end yields no results
+        Ok(matches
+            .into_iter()
+            .skip(options.offset)
+            .take(limit)
+            .collect())
+    }
+
+    /// Drops the `entries` that `filter` rejects
+    pub fn retain_filtered(&self, entries: &mut Vec<DirEntry>, filter: &QueryFilter) -> Result<()> {
+        let now = Utc::now();
+        entries.retain(|entry| filter.keeps(entry, &now));
+
+        if let Some(kind) = filter.project_type {
+            let conn = self.conn();
+            let mut stmt = conn.prepare(
+                "SELECT path FROM dirs WHERE instr(',' || project_type || ',', ',' || ?1 || ',') > 0",
+            )?;
+            let paths: HashSet<String> = stmt
+                .query_map(params![kind.name()], |row| row.get(0))?
+                .filter_map(Result::ok)
+                .collect();
+            entries.retain(|entry| paths.contains(&entry.path));
+        }
+        Ok(())
+    }
+
+    /// `entries` grouped by project root: each goes under the outermost
+    /// tracked directory with a detected project type that contains it, so
+    /// every package of a monorepo lands under the repository. Groups come
+    /// in the order of their first member, keeping a ranked list ranked.
+    pub fn group_by_root(&self, entries: Vec<DirEntry>) -> Result<Vec<RootGroup>> {
+        let roots: HashSet<String> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare("SELECT path FROM dirs WHERE project_type IS NOT NULL")?;
+            let roots = stmt
+                .query_map([], |row| row.get(0))?
+                .filter_map(Result::ok)
+                .collect();
+            roots
+        };
+
+        let mut groups: Vec<RootGroup> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for entry in entries {
+            let root = Path::new(&entry.path)
+                .ancestors()
+                .filter_map(Path::to_str)
+                .filter(|ancestor| roots.contains(*ancestor))
+                .last()
+                .unwrap_or(&entry.path)
+                .to_string();
+            match index.get(&root) {
+                Some(&i) => groups[i].members.push(entry),
+                None => {
+                    index.insert(root.clone(), groups.len());
+                    groups.push(RootGroup {
+                        root,
+                        members: vec![entry],
+                    });
+                }
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Up to `limit` results of `query`, each with the character indices of
+    /// the path that matched, for highlighting: the fuzzy match's characters,
+    /// or the keyword's first occurrence (ignoring case). Empty when the
+    /// match isn't visible in the path (a bookmark or `$CDPATH` result).
+    #[allow(dead_code)] // library API; the CLI prints plain paths
+    pub fn query_with_matches(
+        &self,
+        keywords: &[String],
+        limit: usize,
+    ) -> Result<Vec<(DirEntry, Vec<usize>)>> {
+        let options = QueryOptions {
+            limit: Some(limit),
+            ..QueryOptions::default()
+        };
+        let results = self.query_with(keywords, &options)?;
+
+        let (terms, _) = split_negations(&self.prepare_keywords(keywords));
+        let keyword = nav::normalize_path(&terms.join(" "));
+        Ok(results
+            .into_iter()
+            .map(|entry| {
+                let indices = self
+                    .matcher
+                    .fuzzy_indices(&entry.path, &keyword)
+                    .map(|(_, indices)| indices)
+                    .or_else(|| substring_indices(&entry.path, &keyword))
+                    .unwrap_or_default();
+                (entry, indices)
+            })
+            .collect())
+    }
+
+    /// Keywords as the matching stages see them: trimmed (`x ""` must not
+    /// fuzzy-match everything), `query_aliases` expanded and cut to
+    /// `max_query_len` characters in total, since fuzzy matching a pasted
+    /// blob against every path takes far too long
+    fn prepare_keywords(&self, keywords: &[String]) -> Vec<String> {
+        let keywords = self.config.expand_query_aliases(trim_keywords(keywords));
+        let mut budget = self.config.max_query_len;
+        let mut capped = Vec::with_capacity(keywords.len());
+        for keyword in keywords {
+            let len = keyword.chars().count();
+            if len <= budget {
+                budget -= len;
+                capped.push(keyword);
+                continue;
+            }
+
+            log::warn!(
+                "query longer than max_query_len ({} characters); ignoring the rest",
+                self.config.max_query_len
+            );
+            if budget > 0 {
+                capped.push(keyword.chars().take(budget).collect());
+            }
+            break;
+        }
+        capped
+    }
+
+    /// `ranked_matches`, reused for `QUERY_CACHE_TTL` while nothing is written
+    fn cached_ranked_matches(
+        &self,
+        keywords: &[String],
+        options: &QueryOptions,
+    ) -> Result<Vec<DirEntry>> {
+        let key = format!(
+            "{:?}|{:?}|{:?}|{}|{:?}|{:?}",
+            keywords,
+            options.prefer_cwd,
+            options.age_weight,
+            options.any,
+            options.timeout,
+            options.filter
+        );
+        let stamp = self.write_stamp()?;
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(results) = cache.get(&key, stamp) {
+            return Ok(results);
+        }
+
+        let results = self.ranked_matches(keywords, options, &mut QueryTrace::default())?;
+        cache.insert(key, stamp, results.clone());
+        Ok(results)
+    }
+
+    /// Changes with every write, through this handle (`total_changes`) or
+    /// any other connection (`data_version`)
+    fn write_stamp(&self) -> Result<(u64, i64)> {
+        let conn = self.conn();
+        let data_version = conn.pragma_query_value(None, "data_version", |row| row.get(0))?;
+        Ok((conn.total_changes(), data_version))
+    }
+
+    /// Number of queries that scanned the table instead of hitting the cache
+    #[cfg(test)]
+    #[allow(dead_code)] // only the library's tests look at it
+    pub(crate) fn scans(&self) -> usize {
+        self.scans.load(Ordering::Relaxed)
+    }
+
+    /// How `query_with` would resolve `keywords`: the winning stage,
+    /// candidates per stage and the best scores. Never served from the cache.
+    pub fn explain(&self, keywords: &[String], options: &QueryOptions) -> Result<QueryTrace> {
+        // Checked before the directories, as `xneo query` does
+        let shortcut = self.shortcut(&trim_keywords(keywords))?;
+        let keywords = self.prepare_keywords(keywords);
+        let mut trace = QueryTrace {
+            keywords: keywords.clone(),
+            bookmark: match &shortcut {
+                Some(Shortcut::Bookmark { name, .. }) => Some(name.clone()),
+                _ => None,
+            },
+            shortcut: shortcut.as_ref().map(Shortcut::kind),
+            ..QueryTrace::default()
+        };
+        if !keywords.is_empty() {
+            self.flush_pending()?;
+            self.ranked_matches(&keywords, options, &mut trace)?;
+        }
+        Ok(trace)
+    }
+
+    /// All matches for `keywords`, best first, noting each stage in `trace`.
+    ///
+    /// Symlinks recorded by `record_symlink_aliases` match under their own
+    /// path but are returned as their target, once.
+    fn ranked_matches(
+        &self,
+        keywords: &[String],
+        options: &QueryOptions,
+        trace: &mut QueryTrace,
+    ) -> Result<Vec<DirEntry>> {
+        let aliases = self.path_aliases()?;
+        let matches = self.match_stages(keywords, options, trace, &aliases)?;
+        log::debug!(
+            "query {:?}: {} matches from the {} stage ({})",
+            keywords,
+            matches.len(),
+            trace.stage.unwrap_or("none"),
+            trace
+                .stages
+                .iter()
+                .map(|stage| format!("{} {}", stage.stage, stage.candidates))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if aliases.is_empty() {
+            return Ok(matches);
+        }
+
+        let mut seen = HashSet::new();
+        Ok(matches
+            .into_iter()
+            .map(|mut entry| {
+                if let Some(target) = aliases.get(&entry.path) {
+                    entry.path = target.clone();
+                }
+                entry
+            })
+            .filter(|entry| seen.insert(entry.path.clone()))
+            .collect())
+    }
+
+    /// Pairs `entries` with the score they are ordered by: their rank, which
+    /// for the `MTIME_TOP_K` best-ranked is scaled with `use_mtime` by how
+    /// recently each directory was modified, and with `prefer_git_root` by
+    /// how close each is to the root of the git repository holding it. Only
+    /// those are stat'ed, so a broad query stays cheap. The stored rank
+    /// itself is left alone.
+    fn freshen(&self, entries: Vec<DirEntry>, now: &DateTime<Utc>) -> Vec<(DirEntry, f64)> {
+        let mut scored: Vec<(DirEntry, f64)> = entries
+            .into_iter()
+            .map(|entry| {
+                let rank = entry.rank;
+                (entry, rank)
+            })
+            .collect();
+        if !self.config.use_mtime && !self.config.prefer_git_root {
+            return scored;
+        }
+
+        let mut best: Vec<&mut (DirEntry, f64)> = scored.iter_mut().collect();
+        best.sort_by(|a, b| by_rank(&a.0, &b.0));
+        for (entry, score) in best.into_iter().take(MTIME_TOP_K) {
+            if self.config.use_mtime {
+                let modified = std::fs::metadata(&entry.path).and_then(|meta| meta.modified());
+                if let Ok(modified) = modified {
+                    let modified: DateTime<Utc> = modified.into();
+                    let days = (*now - modified).num_seconds().max(0) as f64 / 86_400.0;
+                    *score *= 1.0 + MTIME_WEIGHT / (days + 1.0);
+                }
+            }
+            if self.config.prefer_git_root {
+                if let Some(depth) = git_root_depth(Path::new(&entry.path)) {
+                    *score *= 1.0 + GIT_ROOT_WEIGHT / (depth as f64 + 1.0);
+                }
+            }
+        }
+        scored
+    }
+
+    /// `freshen`ed `matches`, best score first
+    fn rank_matches(&self, matches: Vec<DirEntry>, now: &DateTime<Utc>) -> Vec<(DirEntry, f64)> {
+        let mut scored = self.freshen(matches, now);
+        scored.sort_by(by_score);
+        scored
+    }
+
+    /// Symlink path -> target path, for every target still tracked
+    fn path_aliases(&self) -> Result<HashMap<String, String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT a.alias, a.target FROM path_aliases a JOIN dirs d ON d.path = a.target",
         )?;
+        let aliases = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(aliases)
+    }
 
-        let all_entries: Vec<DirEntry> = stmt
-            .query_map([], |row| {
-                let path: String = row.get(0)?;
-                let last_access: DateTime<Utc> = row.get(1)?;
-                let visits: u32 = row.get(2)?;
-                let rank = self.calculate_rank(visits, &last_access, &Utc::now());
-
-                Ok(DirEntry {
-                    path,
-                    last_access,
-                    visits,
-                    rank,
-                })
-            })?
+    /// The matching stages behind `ranked_matches`, run over tracked paths and
+    /// the symlink `aliases` to them.
+    ///
+    /// When the first of several keywords names a bookmark, the rest are only
+    /// matched against directories below the bookmarked path (`work api`).
+    fn match_stages(
+        &self,
+        keywords: &[String],
+        options: &QueryOptions,
+        trace: &mut QueryTrace,
+        aliases: &HashMap<String, String>,
+    ) -> Result<Vec<DirEntry>> {
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        let (mut keywords, excluded) = split_negations(keywords);
+        if keywords.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut scope = None;
+        if keywords.len() > 1 {
+            if let Some(path) = self.get_bookmark(&keywords[0])? {
+                scope = Some(PathBuf::from(nav::normalize_path(&nav::expand_path(&path))));
+                trace.scope = scope.as_ref().map(|root| root.display().to_string());
+                keywords.remove(0);
+            }
+        }
+        let in_scope = |path: &Path| {
+            scope
+                .as_deref()
+                .is_none_or(|root| path != root && path.starts_with(root))
+        };
+
+        // Fast path: an explicit absolute directory needs no scan or ranking
+        let joined = nav::normalize_path(&keywords.join(" "));
+        if Path::new(&joined).is_absolute() && Path::new(&joined).is_dir() {
+            let mut found = vec![self.entry_for_existing_path(&joined)?];
+            self.retain_filtered(&mut found, &options.filter)?;
+            trace.stage("absolute_path", found.len());
+            trace.resolve("absolute_path", ranked(&found));
+            return Ok(found);
+        }
+
+        // Get all entries
+        let now = Utc::now();
+        let age_weight = options.age_weight.unwrap_or(DEFAULT_AGE_WEIGHT);
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs ORDER BY visits_total DESC, path"
+        ))?;
+
+        let mut tracked: Vec<DirEntry> = stmt
+            .query_map([], map_dir_row)?
             .filter_map(Result::ok)
+            .map(|entry| {
+                let mut entry = self.with_fresh_rank(entry, &now, age_weight);
+                if let Some(cwd) = &options.prefer_cwd {
+                    entry.rank *= cwd_boost(&entry.path, cwd);
+                }
+                entry
+            })
+            .collect();
+        // Release the connection; filtering and the $CDPATH stage look rows up
+        drop(stmt);
+        drop(conn);
+
+        // Filter candidates before matching, so that when every hit of an
+        // early stage is filtered out a later stage can still supply results
+        self.retain_filtered(&mut tracked, &options.filter)?;
+
+        // A symlink is a candidate of its own, ranked like its target
+        let by_path: HashMap<&str, &DirEntry> = tracked
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let mut symlinks: Vec<DirEntry> = aliases
+            .iter()
+            .filter_map(|(alias, target)| {
+                let entry = by_path.get(target.as_str())?;
+                Some(DirEntry {
+                    path: alias.clone(),
+                    ..(*entry).clone()
+                })
+            })
             .collect();
+        symlinks.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let all_entries: Vec<DirEntry> = tracked
+            .into_iter()
+            .chain(symlinks)
+            .filter(|entry: &DirEntry| {
+                let path = entry.path.to_lowercase();
+                !excluded.iter().any(|term| path.contains(term.as_str()))
+                    && in_scope(Path::new(&entry.path))
+            })
+            .collect();
+
+        // With a deadline, scan the best-ranked candidates first so stopping
+        // early keeps the likeliest results
+        let mut all_entries = all_entries;
+        let deadline = options.timeout.map(|timeout| {
+            all_entries.sort_by(by_rank);
+            Instant::now() + timeout
+        });
+        let expired = |scanned: usize| {
+            let expired = scanned % DEADLINE_CHECK_INTERVAL == DEADLINE_CHECK_INTERVAL - 1
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if expired {
+                log::debug!("query deadline passed after {} candidates", scanned + 1);
+            }
+            expired
+        };
+
+        if options.any && keywords.len() > 1 {
+            let matches = any_keyword_matches(self.freshen(all_entries, &now), &keywords);
+            trace.stage("any", matches.len());
+            if !matches.is_empty() {
+                trace.resolve("any", scores(&matches));
+            }
+            return Ok(unscored(matches));
+        }
 
-        let keyword = keywords.join(" ");
+        let keyword = nav::normalize_path(&keywords.join(" "));
         let mut matches = Vec::new();
 
         // 1. Exact match
@@ -148,15 +1353,21 @@ impl Database {
             }
         }
 
+        trace.stage("exact", matches.len());
         if !matches.is_empty() {
-            matches.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
-            return Ok(matches);
+            let matches = self.rank_matches(matches, &now);
+            trace.resolve("exact", scores(&matches));
+            return Ok(unscored(matches));
         }
 
         // 2. Exact directory name match
-        let mut dir_matches = HashSet::new();
+        // Ordered so the stage's results don't depend on hashing
+        let mut dir_matches = BTreeSet::new();
         for entry in &all_entries {
-            for ancestor in Path::new(&entry.path).ancestors() {
+            for ancestor in Path::new(&entry.path)
+                .ancestors()
+                .take_while(|ancestor| in_scope(ancestor))
+            {
                 if let Some(dir_name) = ancestor.file_name().and_then(|s| s.to_str()) {
                     if dir_name == keyword {
                         if let Some(ancestor_str) = ancestor.to_str() {
@@ -173,96 +1384,507 @@ impl Database {
             }
         }
 
-        if !matches.is_empty() {
-            matches.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
-            return Ok(matches);
-        }
+        trace.stage("dir_name", matches.len());
+        if !matches.is_empty() {
+            let matches = self.rank_matches(matches, &now);
+            trace.resolve("dir_name", scores(&matches));
+            return Ok(unscored(matches));
+        }
+
+        // 3. Fuzzy match (if enabled); very short keywords match nearly
+        // everything, so they go straight to the substring stage
+        if self.config.enable_fuzzy_matching
+            && keyword.chars().count() >= self.config.min_fuzzy_query_len
+        {
+            let mut candidates = Vec::new();
+            let mut match_scores = Vec::new();
+
+            for (scanned, entry) in all_entries.iter().enumerate() {
+                if expired(scanned) {
+                    trace.timed_out = true;
+                    break;
+                }
+                if let Some(score) = self.matcher.fuzzy_match(&entry.path, &keyword) {
+                    let mut match_score = score as f64;
+                    let basename = Path::new(&entry.path).file_name().and_then(|s| s.to_str());
+                    if basename.is_some_and(|name| name.contains(keyword.as_str())) {
+                        match_score *= EXACT_CASE_BASENAME_BONUS;
+                    }
+                    candidates.push(entry.clone());
+                    match_scores.push(match_score);
+                }
+            }
+
+            let mut fuzzy_matches: Vec<(DirEntry, f64)> = self
+                .freshen(candidates, &now)
+                .into_iter()
+                .zip(match_scores)
+                .map(|((entry, score), match_score)| {
+                    let combined_score = match_score * score;
+                    (entry, combined_score)
+                })
+                .collect();
+
+            fuzzy_matches.sort_by(by_score);
+            trace.stage("fuzzy", fuzzy_matches.len());
+            if !fuzzy_matches.is_empty() {
+                trace.resolve("fuzzy", scores(&fuzzy_matches));
+            }
+            matches = unscored(fuzzy_matches);
+        }
+
+        // 4. Substring match (as a fallback)
+        if matches.is_empty() {
+            let keyword = keyword.to_lowercase();
+            for (scanned, entry) in all_entries.iter().enumerate() {
+                if expired(scanned) {
+                    trace.timed_out = true;
+                    break;
+                }
+                let path = entry.path.to_lowercase();
+                let found = if self.config.substring_at_word_start {
+                    contains_at_word_start(&path, &keyword)
+                } else {
+                    path.contains(&keyword)
+                };
+                if found {
+                    matches.push(entry.clone());
+                }
+            }
+            let scored = self.rank_matches(matches, &now);
+            trace.stage("substring", scored.len());
+            if !scored.is_empty() {
+                trace.resolve("substring", scores(&scored));
+            }
+            matches = unscored(scored);
+        }
+
+        // 5. Subdirectories of $CDPATH entries named like the keyword
+        if matches.is_empty() && self.config.use_cdpath {
+            if let Some(cdpath) = std::env::var_os("CDPATH") {
+                for path in nav::cdpath_matches(&keyword, &cdpath)
+                    .into_iter()
+                    .filter(|path| in_scope(path))
+                {
+                    matches.push(self.entry_for_existing_path(&path.to_string_lossy())?);
+                }
+                trace.stage("cdpath", matches.len());
+                if !matches.is_empty() {
+                    trace.resolve("cdpath", ranked(&matches));
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// The tracked row for `path`, or a fresh unvisited entry if it isn't tracked
+    fn entry_for_existing_path(&self, path: &str) -> Result<DirEntry> {
+        let now = Utc::now();
+        let row = self
+            .conn()
+            .query_row(
+                &format!("SELECT {DIR_COLUMNS} FROM dirs WHERE path = ?1"),
+                params![path],
+                map_dir_row,
+            )
+            .optional()?;
+
+        Ok(match row {
+            Some(entry) => self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT),
+            None => DirEntry {
+                path: path.to_string(),
+                last_access: now,
+                visits: 0,
+                rank: 0.0,
+            },
+        })
+    }
+
+    /// `entry` with its persisted rank replaced by the exact rank at `now`
+    fn with_fresh_rank(&self, entry: DirEntry, now: &DateTime<Utc>, age_weight: f64) -> DirEntry {
+        let rank = self.calculate_rank(entry.visits, &entry.last_access, now, age_weight);
+        DirEntry { rank, ..entry }
+    }
+
+    fn calculate_rank(
+        &self,
+        visits: u32,
+        last_access: &DateTime<Utc>,
+        now: &DateTime<Utc>,
+        age_weight: f64,
+    ) -> f64 {
+        let (frequency, recency) = rank_components(visits, last_access, now, age_weight);
+        frequency + recency
+    }
+
+    /// How the rank of `path` breaks down at `now` with the default weights;
+    /// an untracked path has no visits and a zero rank
+    pub fn rank_breakdown(&self, path: &str, now: &DateTime<Utc>) -> Result<RankBreakdown> {
+        let path = nav::normalize_path(path);
+        let row = self
+            .conn()
+            .query_row(
+                &format!("SELECT {DIR_COLUMNS} FROM dirs WHERE path = ?1"),
+                params![path],
+                map_dir_row,
+            )
+            .optional()?;
+
+        let Some(entry) = row else {
+            return Ok(RankBreakdown {
+                path,
+                visits: 0,
+                last_access: None,
+                frequency: 0.0,
+                recency: 0.0,
+                total: 0.0,
+            });
+        };
+        let (frequency, recency) =
+            rank_components(entry.visits, &entry.last_access, now, DEFAULT_AGE_WEIGHT);
+        Ok(RankBreakdown {
+            path,
+            visits: entry.visits,
+            last_access: Some(entry.last_access),
+            frequency,
+            recency,
+            total: frequency + recency,
+        })
+    }
+
+    pub fn find_stale(&self) -> Result<Vec<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT path FROM dirs")?;
+        let paths = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect::<Vec<String>>();
+
+        let checked = paths.len();
+        let mut stale_paths = Vec::new();
+        for path_str in paths {
+            if !Path::new(&path_str).exists() {
+                stale_paths.push(path_str);
+            }
+        }
+
+        log::debug!(
+            "{} of {} tracked paths are stale",
+            stale_paths.len(),
+            checked
+        );
+        Ok(stale_paths)
+    }
+
+    /// Tracked paths that are existing but empty directories. Directories that
+    /// can't be read (permissions) are left alone.
+    pub fn find_empty(&self) -> Result<Vec<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT path FROM dirs")?;
+        let paths = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect::<Vec<String>>();
+
+        Ok(paths
+            .into_iter()
+            .filter(|path| {
+                std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+            })
+            .collect())
+    }
+
+    pub fn purge(&mut self, paths_to_delete: &[String]) -> Result<usize> {
+        self.purge_with_progress(paths_to_delete, |_| {})
+    }
+
+    /// Like `purge`, deleting `purge_batch_size` paths per statement and
+    /// calling `progress` with the number of paths handled after each batch.
+    /// All batches share one transaction, so a failure removes nothing.
+    pub fn purge_with_progress(
+        &mut self,
+        paths_to_delete: &[String],
+        mut progress: impl FnMut(usize),
+    ) -> Result<usize> {
+        self.ensure_writable()?;
+        if paths_to_delete.is_empty() {
+            return Ok(0);
+        }
+
+        let batch_size = self.config.purge_batch_size.clamp(1, MAX_PURGE_BATCH_SIZE);
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut deleted_count = 0;
+        let mut handled = 0;
+
+        // Only the latest purge can be undone
+        tx.execute("DELETE FROM undo_dirs", [])?;
+
+        for batch in paths_to_delete.chunks(batch_size) {
+            let placeholders = vec!["?"; batch.len()].join(", ");
+            let params = rusqlite::params_from_iter(batch);
+            tx.prepare_cached(&format!(
+                "INSERT OR REPLACE INTO undo_dirs ({DIR_COLUMNS})
+                 SELECT {DIR_COLUMNS} FROM dirs WHERE path IN ({placeholders})"
+            ))?
+            .execute(params.clone())?;
+            deleted_count += tx
+                .prepare_cached(&format!("DELETE FROM dirs WHERE path IN ({placeholders})"))?
+                .execute(params)?;
+
+            handled += batch.len();
+            progress(handled);
+        }
+
+        tx.commit()?;
+        log::debug!(
+            "purged {} of {} paths in batches of {}",
+            deleted_count,
+            paths_to_delete.len(),
+            batch_size
+        );
+        Ok(deleted_count)
+    }
+
+    /// Restores the rows removed by the most recent purge, with their original
+    /// visit counts and timestamps. Paths visited again since then keep their
+    /// new row. Returns how many rows were restored.
+    pub fn undo(&mut self) -> Result<usize> {
+        self.ensure_writable()?;
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        let restored = tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO dirs ({DIR_COLUMNS}) SELECT {DIR_COLUMNS} FROM undo_dirs"
+            ),
+            [],
+        )?;
+        tx.execute("DELETE FROM undo_dirs", [])?;
 
-        // 3. Fuzzy match (if enabled)
-        if self.config.enable_fuzzy_matching {
-            let matcher = SkimMatcherV2::default();
-            let mut fuzzy_matches = Vec::new();
+        tx.commit()?;
+        Ok(restored)
+    }
 
-            for entry in &all_entries {
-                if let Some(score) = matcher.fuzzy_match(&entry.path, &keyword) {
-                    let combined_score = (score as f64) * entry.rank;
-                    fuzzy_matches.push((entry.clone(), combined_score));
-                }
+    /// Groups of rows whose paths differ only by case, each sorted with the
+    /// spelling to keep first (the most visited, then the latest). A group
+    /// holds only the spellings for which `same_dir(kept, other)` holds,
+    /// since elsewhere they are distinct directories.
+    fn case_duplicate_groups(
+        &self,
+        mut same_dir: impl FnMut(&Path, &Path) -> bool,
+    ) -> Result<Vec<Vec<DirEntry>>> {
+        let mut groups: HashMap<String, Vec<DirEntry>> = HashMap::new();
+        {
+            let conn = self.conn();
+            let mut stmt = conn.prepare(&format!("SELECT {DIR_COLUMNS} FROM dirs"))?;
+            for entry in stmt.query_map([], map_dir_row)? {
+                let entry = entry?;
+                groups
+                    .entry(entry.path.to_lowercase())
+                    .or_default()
+                    .push(entry);
             }
-
-            fuzzy_matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-            matches = fuzzy_matches.into_iter().map(|(entry, _)| entry).collect();
         }
 
-        // 4. Substring match (as a fallback)
-        if matches.is_empty() {
-            for entry in &all_entries {
-                if entry.path.to_lowercase().contains(&keyword.to_lowercase()) {
-                    matches.push(entry.clone());
-                }
+        let mut duplicates = Vec::new();
+        for mut group in groups.into_values().filter(|group| group.len() > 1) {
+            group.sort_by(|a, b| {
+                b.visits
+                    .cmp(&a.visits)
+                    .then(b.last_access.cmp(&a.last_access))
+            });
+            let kept = group[0].path.clone();
+            group.retain(|entry| {
+                entry.path == kept || same_dir(Path::new(&kept), Path::new(&entry.path))
+            });
+            if group.len() > 1 {
+                duplicates.push(group);
             }
-            matches.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
         }
+        Ok(duplicates)
+    }
 
-        Ok(matches.into_iter().take(20).collect()) // Limit the number of results
+    /// How many rows `merge_case_duplicates` would merge away
+    pub fn count_case_duplicates(
+        &self,
+        same_dir: impl FnMut(&Path, &Path) -> bool,
+    ) -> Result<usize> {
+        let groups = self.case_duplicate_groups(same_dir)?;
+        Ok(groups.iter().map(|group| group.len() - 1).sum())
     }
 
-    fn calculate_rank(&self, visits: u32, last_access: &DateTime<Utc>, now: &DateTime<Utc>) -> f64 {
-        let age_in_hours = (now.timestamp() - last_access.timestamp()) as f64 / 3600.0;
-        let frequency_score = (visits as f64).ln() + 1.0; // Log-scale visit count
-        let recency_score = 1.0 / (age_in_hours + 1.0); // Time decay
+    /// Merges rows whose paths differ only by case, for filesystems where they
+    /// name the same directory: `same_dir(kept, other)` is asked about each
+    /// other spelling, so every path is judged by its own filesystem. The
+    /// spelling with the most visits is kept and gets the summed visits and
+    /// latest access. Returns how many rows were merged away.
+    pub fn merge_case_duplicates(
+        &mut self,
+        same_dir: impl FnMut(&Path, &Path) -> bool,
+    ) -> Result<usize> {
+        self.ensure_writable()?;
+        let now = Utc::now();
+        let groups = self.case_duplicate_groups(same_dir)?;
+        let mut conn = self.conn();
+
+        let tx = conn.transaction()?;
+        let mut merged = 0;
+        for group in &groups {
+            let kept = &group[0];
+            let visits: u32 = group.iter().map(|entry| entry.visits).sum();
+            let last_access = group.iter().map(|entry| entry.last_access).max().unwrap();
+
+            for duplicate in &group[1..] {
+                tx.execute("DELETE FROM dirs WHERE path = ?1", params![duplicate.path])?;
+                tx.execute(
+                    "UPDATE events SET path = ?1 WHERE path = ?2",
+                    params![kept.path, duplicate.path],
+                )?;
+                merged += 1;
+            }
+
+            let rank = self.calculate_rank(visits, &last_access, &now, DEFAULT_AGE_WEIGHT);
+            tx.execute(
+                "UPDATE dirs SET visits_total = ?1, last_access = ?2, rank = ?3 WHERE path = ?4",
+                params![visits, last_access, rank, kept.path],
+            )?;
+        }
 
-        frequency_score * 0.7 + recency_score * 0.3
+        tx.commit()?;
+        Ok(merged)
     }
 
-    pub fn find_stale(&self) -> Result<Vec<String>> {
-        let mut stmt = self.conn.prepare("SELECT path FROM dirs")?;
-        let paths = stmt
-            .query_map([], |row| row.get(0))?
-            .filter_map(Result::ok)
-            .collect::<Vec<String>>();
+    /// Rewrites directories, history and bookmarks under `old_prefix` to live
+    /// under `new_prefix` after the tree was moved, in one transaction. A moved
+    /// directory that was already tracked at its new path is merged into it.
+    pub fn move_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<MoveSummary> {
+        self.ensure_writable()?;
+        let old_prefix = nav::normalize_path(old_prefix);
+        let new_prefix = nav::normalize_path(new_prefix);
+        let moved = |path: &str| -> Option<String> {
+            let rest = path.strip_prefix(old_prefix.as_str())?;
+            (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", new_prefix, rest))
+        };
+        if moved(&new_prefix).is_some() {
+            anyhow::bail!("Cannot move {} into itself ({})", old_prefix, new_prefix);
+        }
 
-        let mut stale_paths = Vec::new();
-        for path_str in paths {
-            if !Path::new(&path_str).exists() {
-                stale_paths.push(path_str);
+        let now = Utc::now();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut summary = MoveSummary::default();
+
+        let entries: Vec<DirEntry> = tx
+            .prepare(&format!("SELECT {DIR_COLUMNS} FROM dirs"))?
+            .query_map([], map_dir_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        for entry in &entries {
+            let Some(target) = moved(&entry.path) else {
+                continue;
+            };
+
+            let existing = tx
+                .query_row(
+                    "SELECT visits_total, last_access FROM dirs WHERE path = ?1",
+                    params![target],
+                    |row| Ok((row.get::<_, u32>(0)?, row.get::<_, DateTime<Utc>>(1)?)),
+                )
+                .optional()?;
+            let (visits, last_access) = match existing {
+                Some((visits, last_access)) => {
+                    tx.execute("DELETE FROM dirs WHERE path = ?1", params![target])?;
+                    (visits + entry.visits, last_access.max(entry.last_access))
+                }
+                None => (entry.visits, entry.last_access),
+            };
+
+            let rank = self.calculate_rank(visits, &last_access, &now, DEFAULT_AGE_WEIGHT);
+            tx.execute(
+                "UPDATE dirs SET path = ?1, visits_total = ?2, last_access = ?3, rank = ?4
+                 WHERE path = ?5",
+                params![target, visits, last_access, rank, entry.path],
+            )?;
+            tx.execute(
+                "UPDATE events SET path = ?1 WHERE path = ?2",
+                params![target, entry.path],
+            )?;
+            summary.dirs += 1;
+        }
+
+        let bookmarks: Vec<(String, String)> = tx
+            .prepare("SELECT name, path FROM bookmarks")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (name, path) in bookmarks {
+            // `--literal` bookmarks (`~/work`, `$PROJECTS/api`) match by what
+            // they expand to and keep their variable when it still applies
+            if let Some(target) = moved(&nav::normalize_path(&nav::expand_path(&path))) {
+                tx.execute(
+                    "UPDATE bookmarks SET path = ?1 WHERE name = ?2",
+                    params![with_literal_head(&path, &target), name],
+                )?;
+                summary.bookmarks += 1;
             }
         }
 
-        Ok(stale_paths)
+        tx.commit()?;
+        Ok(summary)
     }
 
-    pub fn purge(&mut self, paths_to_delete: &[String]) -> Result<usize> {
-        if paths_to_delete.is_empty() {
-            return Ok(0);
-        }
-
-        let tx = self.conn.transaction()?;
-        let mut deleted_count = 0;
+    /// Records `(path, visits)` pairs from another tool, all as visited now
+    /// (see `import::parse_autojump`). Visits add to an existing entry's.
+    /// Ignored and missing directories are skipped; returns how many were
+    /// imported.
+    pub fn import_visits(&mut self, visits: &[(String, u32)]) -> Result<usize> {
+        self.ensure_writable()?;
+        let now = Utc::now();
+        let mut imported = 0;
 
         {
-            let mut stmt = tx.prepare_cached("DELETE FROM dirs WHERE path = ?")?;
-            for path in paths_to_delete {
-                let changed_rows = stmt.execute(params![path])?;
-                deleted_count += changed_rows;
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            for (path, count) in visits {
+                let path = nav::normalize_path(path);
+                if self.add_decision(&path) != AddDecision::Record || !Path::new(&path).is_dir() {
+                    continue;
+                }
+
+                let total: u32 = tx.query_row(
+                    "INSERT INTO dirs (path, last_access, visits_total) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(path) DO UPDATE SET
+                        last_access = excluded.last_access,
+                        visits_total = visits_total + excluded.visits_total
+                     RETURNING visits_total",
+                    params![path, now, count],
+                    |row| row.get(0),
+                )?;
+                let rank = self.calculate_rank(total, &now, &now, DEFAULT_AGE_WEIGHT);
+                tx.execute(
+                    "UPDATE dirs SET rank = ?1 WHERE path = ?2",
+                    params![rank, path],
+                )?;
+                imported += 1;
             }
+            tx.commit()?;
         }
 
-        tx.commit()?;
-        Ok(deleted_count)
+        self.maintain_size_limit()?;
+        Ok(imported)
     }
 
-    fn maintain_size_limit(&mut self) -> Result<()> {
+    fn maintain_size_limit(&self) -> Result<()> {
         let count: u32 = self
-            .conn
+            .conn()
             .query_row("SELECT COUNT(*) FROM dirs", [], |row| row.get(0))?;
 
         if count > self.config.max_entries as u32 {
             let excess = count - self.config.max_entries as u32;
-            self.conn.execute(
+            self.conn().execute(
                 "DELETE FROM dirs WHERE path IN (
-                    SELECT path FROM dirs 
-                    ORDER BY last_access ASC 
+                    SELECT path FROM dirs
+                    ORDER BY last_access ASC, path
                     LIMIT ?1
                 )",
                 params![excess],
@@ -272,114 +1894,583 @@ impl Database {
         Ok(())
     }
 
-    fn auto_clean(&mut self) -> Result<usize> {
+    /// Recomputes the persisted rank of every row and records the refresh time
+    pub fn refresh_ranks(&mut self) -> Result<usize> {
+        self.ensure_writable()?;
+        let now = Utc::now();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut updated = 0;
+
+        {
+            let mut select = tx.prepare(&format!("SELECT {DIR_COLUMNS} FROM dirs"))?;
+            let entries: Vec<DirEntry> = select
+                .query_map([], map_dir_row)?
+                .filter_map(Result::ok)
+                .collect();
+
+            let mut update = tx.prepare_cached("UPDATE dirs SET rank = ?1 WHERE path = ?2")?;
+            for entry in entries {
+                let entry = self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT);
+                updated += update.execute(params![entry.rank, entry.path])?;
+            }
+        }
+
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('ranks_refreshed_at', ?1)",
+            params![now],
+        )?;
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    fn ranks_need_refresh(&self) -> Result<bool> {
+        let refreshed_at: Option<DateTime<Utc>> = self
+            .conn()
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'ranks_refreshed_at'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(match refreshed_at {
+            Some(at) => Utc::now() - at > chrono::Duration::hours(RANK_REFRESH_INTERVAL_HOURS),
+            None => true,
+        })
+    }
+
+    /// The `n` highest-ranked directories by persisted rank (see `RANK_REFRESH_INTERVAL_HOURS`)
+    pub fn top(&self, n: usize) -> Result<Vec<DirEntry>> {
+        // SQLite treats a negative limit as no limit
+        let n = i64::try_from(n).unwrap_or(-1);
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs ORDER BY rank DESC, path LIMIT ?1"
+        ))?;
+
+        let entries = stmt
+            .query_map(params![n], map_dir_row)?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Directories not visited since `cutoff`, most visited first: places that
+    /// used to matter but have been neglected
+    pub fn neglected(&self, cutoff: DateTime<Utc>, n: usize) -> Result<Vec<DirEntry>> {
+        let now = Utc::now();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs
+             WHERE last_access < ?1
+             ORDER BY visits_total DESC, last_access DESC, path LIMIT ?2"
+        ))?;
+
+        let entries = stmt
+            .query_map(params![cutoff, n as i64], map_dir_row)?
+            .filter_map(Result::ok)
+            .map(|entry| self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT))
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Tracked paths starting with `prefix` as a plain string (end it with `/`
+    /// to stay inside one directory), sorted
+    pub fn paths_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = nav::normalize_separators(prefix);
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT path FROM dirs WHERE substr(path, 1, length(?1)) = ?1 ORDER BY path",
+        )?;
+        let paths = stmt
+            .query_map(params![prefix], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+
+    /// The most frecent tracked directory below `cwd` named `name`: the
+    /// downward counterpart of `nav::find_ancestor`
+    pub fn find_descendant(&self, name: &str, cwd: &Path) -> Result<Option<DirEntry>> {
+        let now = Utc::now();
+        let cwd = nav::normalize_path(&cwd.to_string_lossy());
+        let prefix = if cwd.ends_with('/') {
+            cwd
+        } else {
+            format!("{}/", cwd)
+        };
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs WHERE substr(path, 1, length(?1)) = ?1"
+        ))?;
+        let best = stmt
+            .query_map(params![prefix], map_dir_row)?
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                Path::new(&entry.path).file_name().and_then(|s| s.to_str()) == Some(name)
+            })
+            .map(|entry| self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT))
+            .max_by(|a, b| a.rank.total_cmp(&b.rank));
+
+        Ok(best)
+    }
+
+    /// Keeps the event log bounded to the retention window ending at `now`
+    fn prune_events(&self, now: DateTime<Utc>) -> Result<()> {
+        let cutoff = now - chrono::Duration::days(self.config.history_retention_days as i64);
+        self.conn()
+            .execute("DELETE FROM events WHERE timestamp < ?1", params![cutoff])?;
+
+        Ok(())
+    }
+
+    /// Recorded jumps, oldest first, optionally only those in the half-open
+    /// window `since <= timestamp < until`
+    pub fn history_events(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<HistoryEvent>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT path, timestamp FROM events
+             WHERE (?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR timestamp < ?2)
+             ORDER BY timestamp ASC, rowid",
+        )?;
+
+        let events = stmt
+            .query_map(params![since, until], |row| {
+                Ok(HistoryEvent {
+                    path: row.get(0)?,
+                    timestamp: row.get(1)?,
+                })
+            })?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(events)
+    }
+
+    /// Number of recorded jumps on each of the last `days` local calendar
+    /// days, oldest first and ending today; days without any count as 0.
+    /// Limited by `history_retention_days`, like `history_events`.
+    pub fn visits_per_day(&self, days: u32) -> Result<Vec<(NaiveDate, u32)>> {
+        if days == 0 {
+            return Ok(Vec::new());
+        }
+        let today = Local::now().date_naive();
+        let first = today
+            .checked_sub_days(chrono::Days::new(u64::from(days - 1)))
+            .with_context(|| format!("{} days back from today is out of range", days))?;
+        let since = first
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+            .map(|midnight| midnight.with_timezone(&Utc));
+
+        let mut counts: BTreeMap<NaiveDate, u32> = first
+            .iter_days()
+            .take(days as usize)
+            .map(|day| (day, 0))
+            .collect();
+        for event in self.history_events(since, None)? {
+            let day = event.timestamp.with_timezone(&Local).date_naive();
+            if let Some(count) = counts.get_mut(&day) {
+                *count += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Removes stale entries unless another process is already doing so or
+    /// did within `AUTO_CLEAN_INTERVAL_MINUTES`, so shells opened together
+    /// don't all scan the filesystem
+    fn auto_clean(&mut self, lock: &Path) -> Result<usize> {
+        let Some(_claim) = claim_auto_clean(lock, Utc::now())? else {
+            log::debug!("skipping auto-clean: claimed by another process or ran recently");
+            return Ok(0);
+        };
         let stale_paths = self.find_stale()?;
         self.purge(&stale_paths)
     }
 
     // Bookmark functions
+    /// SQL collation for bookmark name comparisons (see `bookmark_case_insensitive`)
+    fn name_collation(&self) -> &'static str {
+        if self.config.bookmark_case_insensitive {
+            "COLLATE NOCASE"
+        } else {
+            "COLLATE BINARY"
+        }
+    }
+
     pub fn add_bookmark(&mut self, name: &str, path: &str) -> Result<()> {
-        self.conn.execute(
-            "INSERT OR REPLACE INTO bookmarks (name, path) VALUES (?1, ?2)",
+        self.ensure_writable()?;
+        let name = name.trim();
+        let path = nav::normalize_path(path);
+        let collation = self.name_collation();
+        let conn = self.conn();
+        // A real bookmark takes over a name previously used as an alias
+        conn.execute(
+            &format!("DELETE FROM bookmark_aliases WHERE alias = ?1 {collation}"),
+            params![name],
+        )?;
+        // Re-pointing a bookmark keeps its place, usage and stored spelling
+        let repointed = conn.execute(
+            &format!(
+                "UPDATE bookmarks SET path = ?2 WHERE name = (
+                     SELECT name FROM bookmarks WHERE name = ?1 {collation}
+                     ORDER BY name = ?1 DESC LIMIT 1
+                 )"
+            ),
             params![name, path],
         )?;
+        if repointed == 0 {
+            conn.execute(
+                "INSERT INTO bookmarks (name, path) VALUES (?1, ?2)",
+                params![name, path],
+            )?;
+        }
         Ok(())
     }
 
+    /// `add_bookmark` for `bookmark add`: refuses a `path` that doesn't exist
+    /// (once expanded) unless `allow_missing`, as for an unmounted drive.
+    /// Returns whether the path exists; a missing one is stored unverified.
+    pub fn add_bookmark_checked(
+        &mut self,
+        name: &str,
+        path: &str,
+        allow_missing: bool,
+    ) -> Result<bool> {
+        let exists = Path::new(&nav::expand_path(path)).exists();
+        if !exists && !allow_missing {
+            anyhow::bail!(
+                "Path does not exist: {} (use --allow-missing to add it anyway)",
+                path
+            );
+        }
+        self.add_bookmark(name, path)?;
+        Ok(exists)
+    }
+
+    /// Creates `alias` as another name for the bookmark `existing`.
+    /// Returns `false` if `existing` is not a bookmark or alias.
+    pub fn add_bookmark_alias(&mut self, existing: &str, alias: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        let (existing, alias) = (existing.trim(), alias.trim());
+        let collation = self.name_collation();
+
+        // Aliases always point at the canonical bookmark, never at another alias
+        let target: Option<String> = self
+            .conn()
+            .query_row(
+                &format!(
+                    "SELECT name FROM bookmarks WHERE name = ?1 {collation}
+                     UNION ALL
+                     SELECT target FROM bookmark_aliases WHERE alias = ?1 {collation}
+                     LIMIT 1"
+                ),
+                params![existing],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let Some(target) = target else {
+            return Ok(false);
+        };
+
+        let is_bookmark: bool = self.conn().query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM bookmarks WHERE name = ?1 {collation})"),
+            params![alias],
+            |row| row.get(0),
+        )?;
+        if is_bookmark {
+            anyhow::bail!("'{}' is already a bookmark", alias);
+        }
+
+        // An alias replaces any other spelling of itself
+        self.conn().execute(
+            &format!("DELETE FROM bookmark_aliases WHERE alias = ?1 {collation}"),
+            params![alias],
+        )?;
+        self.conn().execute(
+            "INSERT INTO bookmark_aliases (alias, target) VALUES (?1, ?2)",
+            params![alias, target],
+        )?;
+        Ok(true)
+    }
+
+    /// Puts the bookmark (or alias target) `name` at `position` in
+    /// `get_bookmarks`, or back among the unordered ones with `None`. Equal
+    /// positions fall back to the name. Returns `false` if there's no such
+    /// bookmark.
+    pub fn set_bookmark_position(&mut self, name: &str, position: Option<u32>) -> Result<bool> {
+        self.ensure_writable()?;
+        let Some((canonical, _)) = self.lookup_bookmark(name)? else {
+            return Ok(false);
+        };
+        self.conn().execute(
+            "UPDATE bookmarks SET position = ?1 WHERE name = ?2",
+            params![position, canonical],
+        )?;
+        Ok(true)
+    }
+
     pub fn remove_bookmark(&mut self, name: &str) -> Result<bool> {
-        let changes = self
-            .conn
-            .execute("DELETE FROM bookmarks WHERE name = ?1", params![name])?;
+        self.ensure_writable()?;
+        let name = name.trim();
+        let collation = self.name_collation();
+        let conn = self.conn();
+
+        // The stored spelling of `name`, an exact-case one first
+        let stored = |table: &str, column: &str| -> Result<Option<String>> {
+            Ok(conn
+                .query_row(
+                    &format!(
+                        "SELECT {column} FROM {table} WHERE {column} = ?1 {collation}
+                         ORDER BY {column} = ?1 DESC LIMIT 1"
+                    ),
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        };
+        let bookmark = stored("bookmarks", "name")?;
+        let alias = stored("bookmark_aliases", "alias")?;
+
+        // Removing a bookmark also removes its aliases; a name may also be an alias itself
+        let mut changes = 0;
+        if let Some(bookmark) = &bookmark {
+            changes += conn.execute("DELETE FROM bookmarks WHERE name = ?1", params![bookmark])?;
+            changes += conn.execute(
+                "DELETE FROM bookmark_aliases WHERE target = ?1",
+                params![bookmark],
+            )?;
+        }
+        if let Some(alias) = &alias {
+            changes += conn.execute(
+                "DELETE FROM bookmark_aliases WHERE alias = ?1",
+                params![alias],
+            )?;
+        }
         Ok(changes > 0)
     }
 
     pub fn get_bookmarks(&self) -> Result<Vec<Bookmark>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT name, path FROM bookmarks ORDER BY name")?;
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT name, path, use_count, last_used, position FROM bookmarks
+             ORDER BY position IS NULL, position, name",
+        )?;
 
-        let bookmarks = stmt
+        let mut bookmarks: Vec<Bookmark> = stmt
             .query_map([], |row| {
                 Ok(Bookmark {
                     name: row.get(0)?,
                     path: row.get(1)?,
+                    aliases: Vec::new(),
+                    use_count: row.get(2)?,
+                    last_used: row.get(3)?,
+                    position: row.get(4)?,
                 })
             })?
             .filter_map(Result::ok)
             .collect();
 
+        let mut stmt = conn.prepare("SELECT alias, target FROM bookmark_aliases ORDER BY alias")?;
+        let aliases = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .filter_map(Result::ok);
+
+        for (alias, target) in aliases {
+            if let Some(bookmark) = bookmarks.iter_mut().find(|b| b.name == target) {
+                bookmark.aliases.push(alias);
+            }
+        }
+
         Ok(bookmarks)
     }
 
+    /// Finds malformed bookmarks, missing targets and targets bookmarked
+    /// under more than one name
+    pub fn check_bookmarks(&self) -> Result<BookmarkCheck> {
+        let mut check = BookmarkCheck::default();
+        let mut by_target: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for bookmark in self.get_bookmarks()? {
+            if bookmark.name.trim().is_empty() || bookmark.path.trim().is_empty() {
+                check.invalid.push(bookmark.name);
+                continue;
+            }
+            if !bookmark.is_verified() {
+                check.missing.push(bookmark.name.clone());
+            }
+            let target = nav::normalize_path(&nav::expand_path(&bookmark.path));
+            by_target.entry(target).or_default().push(bookmark.name);
+        }
+
+        check.duplicates = by_target
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .collect();
+        Ok(check)
+    }
+
+    /// Deletes the bookmarks `check_bookmarks` reports as invalid, with their
+    /// aliases. Returns how many bookmarks were removed.
+    pub fn fix_bookmarks(&mut self) -> Result<usize> {
+        self.ensure_writable()?;
+        let conn = self.conn();
+        conn.execute(
+            "DELETE FROM bookmark_aliases WHERE target IN (
+                SELECT name FROM bookmarks WHERE trim(name) = '' OR trim(path) = ''
+            )",
+            [],
+        )?;
+        let removed = conn.execute(
+            "DELETE FROM bookmarks WHERE trim(name) = '' OR trim(path) = ''",
+            [],
+        )?;
+        Ok(removed)
+    }
+
+    /// Looks up a bookmark or alias by name, ignoring surrounding whitespace.
+    /// An exact-case match wins over a case-insensitive one when both exist.
     pub fn get_bookmark(&self, name: &str) -> Result<Option<String>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT path FROM bookmarks WHERE name = ?1")?;
-        let mut rows = stmt.query_map(params![name], |row| row.get(0))?;
+        Ok(self.lookup_bookmark(name)?.map(|(_, path)| path))
+    }
 
-        if let Some(row) = rows.next() {
-            Ok(Some(row?))
-        } else {
-            Ok(None)
+    /// `get_bookmark` for a jump: also counts the use on the bookmark itself
+    /// (even when reached through an alias) for `xneo bookmark stats`
+    pub fn resolve_bookmark(&self, name: &str) -> Result<Option<String>> {
+        let Some((canonical, path)) = self.lookup_bookmark(name)? else {
+            return Ok(None);
+        };
+        self.record_bookmark_use(&canonical)?;
+        Ok(Some(path))
+    }
+
+    /// Counts a jump through the bookmark named `name` (canonical)
+    pub fn record_bookmark_use(&self, name: &str) -> Result<()> {
+        // Read-only databases still resolve, they just don't count
+        if self.newer_schema.is_none() {
+            self.conn().execute(
+                "UPDATE bookmarks SET use_count = use_count + 1, last_used = ?1 WHERE name = ?2",
+                params![Utc::now(), name],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The shortcut `xneo query` takes for trimmed `keywords`, if any:
+    /// relative dots, then an exact bookmark, then bookmark name prefixes.
+    /// Only single keywords qualify. Doesn't count bookmark use.
+    pub fn shortcut(&self, keywords: &[String]) -> Result<Option<Shortcut>> {
+        let [keyword] = keywords else {
+            return Ok(None);
+        };
+        if nav::is_dots(keyword) {
+            return Ok(Some(Shortcut::Dots));
         }
+        if let Some((name, path)) = self.lookup_bookmark(keyword)? {
+            return Ok(Some(Shortcut::Bookmark { name, path }));
+        }
+        let paths = self.bookmarks_with_prefix(keyword)?;
+        Ok((!paths.is_empty()).then_some(Shortcut::BookmarkPrefix(paths)))
+    }
+
+    /// The canonical name and path of the bookmark `name` refers to
+    fn lookup_bookmark(&self, name: &str) -> Result<Option<(String, String)>> {
+        let collation = self.name_collation();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT name, path, name = ?1 AS exact FROM bookmarks WHERE name = ?1 {collation}
+             UNION ALL
+             SELECT b.name, b.path, a.alias = ?1 FROM bookmark_aliases a
+             JOIN bookmarks b ON b.name = a.target
+             WHERE a.alias = ?1 {collation}
+             ORDER BY exact DESC
+             LIMIT 1"
+        ))?;
+
+        Ok(stmt
+            .query_row(params![name.trim()], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?)
+    }
+
+    /// Paths of bookmarks (or aliases) whose name starts with `prefix`, by name
+    pub fn bookmarks_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let collation = self.name_collation();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT name, path FROM bookmarks
+             WHERE substr(name, 1, length(?1)) = ?1 {collation}
+             UNION
+             SELECT a.alias, b.path FROM bookmark_aliases a
+             JOIN bookmarks b ON b.name = a.target
+             WHERE substr(a.alias, 1, length(?1)) = ?1 {collation}
+             ORDER BY 1"
+        ))?;
+
+        let mut paths: Vec<String> = Vec::new();
+        for path in stmt.query_map(params![prefix.trim()], |row| row.get::<_, String>(1))? {
+            let path = path?;
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        Ok(paths)
     }
 
     // Statistics functions
     pub fn get_stats(&self) -> Result<Stats> {
-        let total_entries: u32 = self
-            .conn
-            .query_row("SELECT COUNT(*) FROM dirs", [], |row| row.get(0))?;
+        self.get_stats_top(DEFAULT_STATS_TOP)
+    }
+
+    /// `get_stats` listing the `n` most and most recently visited directories
+    pub fn get_stats_top(&self, n: usize) -> Result<Stats> {
+        let limit = i64::try_from(n).unwrap_or(i64::MAX);
+        let now = Utc::now();
+        let conn = self.conn();
+        let total_entries: u32 =
+            conn.query_row("SELECT COUNT(*) FROM dirs", [], |row| row.get(0))?;
 
-        let total_visits: u32 = self.conn.query_row(
+        let total_visits: u32 = conn.query_row(
             "SELECT COALESCE(SUM(visits_total), 0) FROM dirs",
             [],
             |row| row.get(0),
         )?;
 
         // Most visited directories
-        let mut stmt = self.conn.prepare(
-            "SELECT path, last_access, visits_total FROM dirs 
-             ORDER BY visits_total DESC LIMIT 10",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs ORDER BY visits_total DESC, path LIMIT ?1"
+        ))?;
 
         let most_visited = stmt
-            .query_map([], |row| {
-                let path: String = row.get(0)?;
-                let last_access: DateTime<Utc> = row.get(1)?;
-                let visits: u32 = row.get(2)?;
-                let rank = self.calculate_rank(visits, &last_access, &Utc::now());
-
-                Ok(DirEntry {
-                    path,
-                    last_access,
-                    visits,
-                    rank,
-                })
-            })?
+            .query_map(params![limit], map_dir_row)?
             .filter_map(Result::ok)
+            .map(|entry| self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT))
             .collect();
 
         // Recently visited directories
-        let mut stmt = self.conn.prepare(
-            "SELECT path, last_access, visits_total FROM dirs 
-             ORDER BY last_access DESC LIMIT 10",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DIR_COLUMNS} FROM dirs ORDER BY last_access DESC, path LIMIT ?1"
+        ))?;
 
         let recently_visited = stmt
-            .query_map([], |row| {
-                let path: String = row.get(0)?;
-                let last_access: DateTime<Utc> = row.get(1)?;
-                let visits: u32 = row.get(2)?;
-                let rank = self.calculate_rank(visits, &last_access, &Utc::now());
-
-                Ok(DirEntry {
-                    path,
-                    last_access,
-                    visits,
-                    rank,
-                })
-            })?
+            .query_map(params![limit], map_dir_row)?
             .filter_map(Result::ok)
+            .map(|entry| self.with_fresh_rank(entry, &now, DEFAULT_AGE_WEIGHT))
             .collect();
 
         Ok(Stats {
@@ -389,5 +2480,310 @@ impl Database {
             recently_visited,
         })
     }
+
+    /// Page usage and row counts behind the database file's size
+    pub fn storage_report(&self) -> Result<StorageReport> {
+        let conn = self.conn();
+        // SQLite integers are signed; these are never negative
+        let pragma = |name: &str| -> Result<u64> {
+            let value: i64 = conn.pragma_query_value(None, name, |row| row.get(0))?;
+            Ok(value as u64)
+        };
+        let count = |table: &str| -> Result<u64> {
+            let rows: i64 =
+                conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })?;
+            Ok(rows as u64)
+        };
+
+        Ok(StorageReport {
+            page_size: pragma("page_size")?,
+            page_count: pragma("page_count")?,
+            free_pages: pragma("freelist_count")?,
+            dir_rows: count("dirs")?,
+            event_rows: count("events")?,
+            bookmark_rows: count("bookmarks")?,
+        })
+    }
+}
+
+/// The weighted frequency and recency parts of a rank, which sum to it
+fn rank_components(
+    visits: u32,
+    last_access: &DateTime<Utc>,
+    now: &DateTime<Utc>,
+    age_weight: f64,
+) -> (f64, f64) {
+    let age_in_hours = (now.timestamp() - last_access.timestamp()) as f64 / 3600.0;
+    let frequency_score = (visits as f64).ln() + 1.0; // Log-scale visit count
+    let recency_score = 1.0 / (age_in_hours + 1.0); // Time decay
+
+    (
+        frequency_score * (1.0 - age_weight),
+        recency_score * age_weight,
+    )
+}
+
+/// Whether the first entry clearly beats the second: its rank is at least
+/// `ratio` times the runner-up's. A single entry is trivially dominant.
+pub fn is_dominant(entries: &[DirEntry], ratio: f64) -> bool {
+    match entries {
+        [] => false,
+        [_] => true,
+        [first, second, ..] => first.rank > 0.0 && first.rank >= second.rank.max(0.0) * ratio,
+    }
+}
+
+/// Character indices of the first occurrence of `needle` in `haystack`,
+/// ignoring case
+fn substring_indices(haystack: &str, needle: &str) -> Option<Vec<usize>> {
+    let haystack: Vec<char> = haystack.chars().collect();
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return None;
+    }
+
+    let same = |a: &char, b: &char| a.to_lowercase().eq(b.to_lowercase());
+    (0..=haystack.len() - needle.len())
+        .find(|&start| {
+            haystack[start..]
+                .iter()
+                .zip(&needle)
+                .all(|(a, b)| same(a, b))
+        })
+        .map(|start| (start..start + needle.len()).collect())
+}
+
+/// Best rank first; equal ranks (and NaN, via `total_cmp`) fall back to the
+/// path so results never depend on scan or hash order
+fn by_rank(a: &DirEntry, b: &DirEntry) -> std::cmp::Ordering {
+    b.rank.total_cmp(&a.rank).then_with(|| a.path.cmp(&b.path))
+}
+
+/// Best score first, then as `by_rank`
+fn by_score(a: &(DirEntry, f64), b: &(DirEntry, f64)) -> std::cmp::Ordering {
+    b.1.total_cmp(&a.1).then_with(|| by_rank(&a.0, &b.0))
+}
+
+/// `(path, rank)` pairs for `QueryTrace::resolve`
+fn ranked(entries: &[DirEntry]) -> impl Iterator<Item = (String, f64)> + '_ {
+    entries.iter().map(|entry| (entry.path.clone(), entry.rank))
+}
+
+/// `(path, score)` pairs for `QueryTrace::resolve`
+fn scores(scored: &[(DirEntry, f64)]) -> impl Iterator<Item = (String, f64)> + '_ {
+    scored
+        .iter()
+        .map(|(entry, score)| (entry.path.clone(), *score))
+}
+
+/// The entries of `scored`, in order
+fn unscored(scored: Vec<(DirEntry, f64)>) -> Vec<DirEntry> {
+    scored.into_iter().map(|(entry, _)| entry).collect()
+}
+
+/// Entries whose path contains at least one of `keywords` (ignoring case),
+/// ranked by how many keywords they contain and then by score
+fn any_keyword_matches(entries: Vec<(DirEntry, f64)>, keywords: &[String]) -> Vec<(DirEntry, f64)> {
+    let keywords: Vec<String> = keywords
+        .iter()
+        .map(|keyword| nav::normalize_separators(keyword).to_lowercase())
+        .collect();
+
+    let mut matches: Vec<(usize, (DirEntry, f64))> = entries
+        .into_iter()
+        .filter_map(|scored| {
+            let path = scored.0.path.to_lowercase();
+            let hits = keywords
+                .iter()
+                .filter(|k| path.contains(k.as_str()))
+                .count();
+            (hits > 0).then_some((hits, scored))
+        })
+        .collect();
+
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| by_score(&a.1, &b.1)));
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Whether `needle` occurs in `haystack` starting at the beginning of a path
+/// component or of a word within one (after `-`, `_`, `.` or a space), so
+/// `app` finds `/x/app` and `/x/my-app` but not `/x/scrapper`.
+fn contains_at_word_start(haystack: &str, needle: &str) -> bool {
+    haystack.match_indices(needle).any(|(start, _)| {
+        haystack[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| matches!(c, '/' | '-' | '_' | '.' | ' '))
+    })
+}
+
+/// Where auto-clean runs are coordinated for the database at `db_path`
+fn auto_clean_lock_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("autoclean")
+}
+
+/// Claims the next auto-clean through `lock`, a file holding the time of the
+/// last claim. Returns the file, locked until dropped, or `None` if another
+/// process holds it or the last claim is within `AUTO_CLEAN_INTERVAL_MINUTES`
+/// of `now`.
+pub(crate) fn claim_auto_clean(lock: &Path, now: DateTime<Utc>) -> Result<Option<std::fs::File>> {
+    use std::io::{Read, Seek, Write};
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock)
+        .with_context(|| format!("Failed to open {:?}", lock))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+        Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+
+    let mut last_claim = String::new();
+    file.read_to_string(&mut last_claim)?;
+    let interval = chrono::Duration::minutes(AUTO_CLEAN_INTERVAL_MINUTES);
+    if DateTime::parse_from_rfc3339(last_claim.trim())
+        .is_ok_and(|at| now - at.with_timezone(&Utc) < interval)
+    {
+        return Ok(None);
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(now.to_rfc3339().as_bytes())?;
+    Ok(Some(file))
+}
+
+/// Where `buffer_adds` queues visits for the database at `db_path`
+fn pending_log_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("pending")
+}
+
+/// `target` written like the `literal` bookmark path it replaces: when
+/// `literal` starts with `~` or a `$VAR` component and `target` is still
+/// under what that expands to, the component is kept (`~/projects/api`);
+/// otherwise `target` as is.
+fn with_literal_head(literal: &str, target: &str) -> String {
+    let head = literal.split('/').next().unwrap_or_default();
+    if !head.starts_with(['~', '$']) {
+        return target.to_string();
+    }
+    let expanded = nav::normalize_path(&nav::expand_path(head));
+    match target.strip_prefix(expanded.as_str()) {
+        Some(rest) if expanded != head && (rest.is_empty() || rest.starts_with('/')) => {
+            format!("{}{}", head, rest)
+        }
+        _ => target.to_string(),
+    }
+}
+
+/// Lock held by `flush_pending` while it applies the log at `pending_log`
+pub(crate) fn flush_lock_path(pending_log: &Path) -> PathBuf {
+    pending_log.with_extension("flushlock")
+}
+
+/// Levels between `dir` and the root of the git repository holding it (0
+/// for the root itself), found by walking up to a `.git`; `None` outside a
+/// repository
+fn git_root_depth(dir: &Path) -> Option<usize> {
+    dir.ancestors()
+        .position(|ancestor| ancestor.join(".git").exists())
+}
+
+/// `path` as `add` stores it: absolute (relative paths are resolved
+/// against the current directory, e.g. `xneo add somedir` from a script)
+/// and normalized
+pub(crate) fn resolve_add_path(path: &str) -> Result<String> {
+    // The hook passes absolute paths, which must work even from a deleted cwd
+    if nav::is_absolute(path) {
+        return Ok(nav::normalize_path(path));
+    }
+    let cwd = std::env::current_dir()
+        .with_context(|| format!("Failed to resolve {:?} against the current directory", path))?;
+    Ok(nav::absolute_path(path, &cwd))
+}
+
+/// Appends one `<RFC 3339 time>\t<path>` line. Appends this small are not
+/// interleaved between processes, so no lock is needed.
+fn append_pending(log: &Path, path: &str, at: DateTime<Utc>) -> Result<()> {
+    use std::io::Write;
+
+    let line = format!("{}\t{}\n", at.to_rfc3339(), path);
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to queue visit in {:?}", log))
+}
+
+/// Parses a line written by `append_pending`, skipping damaged ones
+fn parse_pending(line: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (at, path) = line.split_once('\t')?;
+    let at = DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc);
+    (!path.is_empty()).then_some((at, path))
+}
+
+/// Keywords with surrounding whitespace removed and blank ones dropped
+pub fn trim_keywords(keywords: &[String]) -> Vec<String> {
+    keywords
+        .iter()
+        .map(|keyword| keyword.trim())
+        .filter(|keyword| !keyword.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits query keywords into search terms and lowercased exclusions.
+///
+/// A keyword starting with `-` excludes paths containing the rest of it
+/// (`api -test`), unless it names an existing path. Everything after a `--`
+/// keyword is taken literally, so `-- -scratch` searches for `-scratch`.
+fn split_negations(keywords: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut terms = Vec::new();
+    let mut excluded = Vec::new();
+    let mut literal = false;
+
+    for keyword in keywords {
+        if literal {
+            terms.push(keyword.clone());
+        } else if keyword == "--" {
+            literal = true;
+        } else if let Some(term) = keyword
+            .strip_prefix('-')
+            .filter(|term| !term.is_empty() && !Path::new(keyword).exists())
+        {
+            excluded.push(term.to_lowercase());
+        } else {
+            terms.push(keyword.clone());
+        }
+    }
+
+    (terms, excluded)
 }
 
+/// Rank multiplier in `[1.0, 2.0]` growing with the number of leading
+/// path components `path` shares with `cwd`.
+fn cwd_boost(path: &str, cwd: &Path) -> f64 {
+    let cwd_components: Vec<_> = cwd
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    if cwd_components.is_empty() {
+        return 1.0;
+    }
+
+    let shared = Path::new(path)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .zip(&cwd_components)
+        .take_while(|(a, b)| a == *b)
+        .count();
+
+    1.0 + shared as f64 / cwd_components.len() as f64
+}
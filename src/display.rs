@@ -0,0 +1,496 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fmt::{self, Write};
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::db::{Bookmark, DirEntry, RankBreakdown, Stats, StorageReport, SCHEMA_VERSION};
+use crate::insights::Insights;
+use crate::nav;
+use crate::theme;
+
+/// Whether progress UI and colors should be drawn: not with `--quiet`, and
+/// not when stdout or stderr is redirected (cron, pipes, log files).
+pub fn is_interactive(quiet: bool) -> bool {
+    !quiet && std::io::stdout().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// A spinner that draws nothing unless `interactive`
+pub fn spinner(interactive: bool, message: &'static str) -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    if !interactive {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap(),
+    );
+    pb.set_message(message);
+    pb
+}
+
+/// A progress bar of `len` steps that draws nothing unless `interactive`
+pub fn progress_bar(interactive: bool, len: u64, message: &'static str) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    if !interactive {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .unwrap(),
+    );
+    pb.set_message(message);
+    pb
+}
+
+/// Default `query --format` template
+pub const DEFAULT_ENTRY_FORMAT: &str = "{path}";
+
+/// Renders one query result through a template with `{path}`, `{visits}`,
+/// `{rank}` and `{last_access}` placeholders; other text is kept verbatim.
+pub fn format_entry(template: &str, entry: &DirEntry) -> String {
+    template
+        .replace("{visits}", &entry.visits.to_string())
+        .replace("{rank}", &format!("{:.3}", entry.rank))
+        .replace("{last_access}", &entry.last_access.to_rfc3339())
+        // Last, so placeholder-like text inside the path is left alone
+        .replace("{path}", &entry.path)
+}
+
+/// Query results followed by the bookmarks not among them, for
+/// `--include-bookmarks`. Bookmarked paths get a `<TAB>[name]` suffix, so the
+/// path is everything before the first tab; bookmarks that aren't results are
+/// listed by path alone since they have no visits or rank to format.
+pub fn blend_bookmarks(
+    entries: &[DirEntry],
+    bookmarks: &[Bookmark],
+    template: &str,
+) -> Vec<String> {
+    let target = |bookmark: &Bookmark| nav::normalize_path(&nav::expand_path(&bookmark.path));
+    let annotate = |line: String, path: &str| {
+        let names: Vec<&str> = bookmarks
+            .iter()
+            .filter(|bookmark| target(bookmark) == path)
+            .map(|bookmark| bookmark.name.as_str())
+            .collect();
+        if names.is_empty() {
+            line
+        } else {
+            format!("{}\t[{}]", line, names.join(", "))
+        }
+    };
+
+    let mut lines: Vec<String> = entries
+        .iter()
+        .map(|entry| annotate(format_entry(template, entry), &entry.path))
+        .collect();
+
+    let mut listed: Vec<String> = entries.iter().map(|entry| entry.path.clone()).collect();
+    for bookmark in bookmarks {
+        let path = target(bookmark);
+        if !listed.contains(&path) {
+            lines.push(annotate(path.clone(), &path));
+            listed.push(path);
+        }
+    }
+
+    lines
+}
+
+/// The line without the `<TAB>[name]` suffix added by `blend_bookmarks`
+pub fn strip_bookmark_annotation(line: &str) -> &str {
+    match line.rsplit_once("\t[") {
+        Some((rest, names)) if names.ends_with(']') => rest,
+        _ => line,
+    }
+}
+
+/// Renders entries as RFC 4180 CSV with a `path,visits,last_access,rank`
+/// header, for `xneo stats --csv`.
+pub fn render_csv(entries: &[DirEntry]) -> String {
+    let mut out = String::from("path,visits,last_access,rank\r\n");
+    for entry in entries {
+        out.push_str(&format!(
+            "{},{},{},{:.3}\r\n",
+            csv_field(&entry.path),
+            entry.visits,
+            entry.last_access.to_rfc3339(),
+            entry.rank
+        ));
+    }
+    out
+}
+
+/// Quotes a CSV field when it contains a comma, quote or line break,
+/// doubling any quotes inside it
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A `name<TAB>fullpath` completion line; shells show the path as the description.
+pub fn format_suggestion(path: &str) -> String {
+    let name = std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_else(|| path.into());
+    format!("{}\t{}", name, path)
+}
+
+/// Renders `xneo version`; `verbose` adds the details useful in bug reports.
+pub fn render_version(verbose: bool, config_path: &Path, db_path: &Path) -> String {
+    let mut out = format!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if verbose {
+        // Writing into a String cannot fail
+        write_version_details(&mut out, config_path, db_path).expect("formatting into a String");
+    }
+    out
+}
+
+fn write_version_details(out: &mut String, config_path: &Path, db_path: &Path) -> fmt::Result {
+    writeln!(out, "Config:         {}", config_path.display())?;
+    writeln!(out, "Database:       {}", db_path.display())?;
+    writeln!(out, "Schema version: {}", SCHEMA_VERSION)?;
+    writeln!(out, "SQLite:         {}", rusqlite::version())
+}
+
+/// Renders the `xneo stats` report.
+pub fn render_stats(stats: &Stats, bookmarks: &[Bookmark]) -> String {
+    let mut out = String::new();
+    // Writing into a String cannot fail
+    write_stats(&mut out, stats, bookmarks).expect("formatting into a String");
+    out
+}
+
+fn write_stats(out: &mut String, stats: &Stats, bookmarks: &[Bookmark]) -> fmt::Result {
+    writeln!(out, "{}", theme::heading("📊 xneo Statistics"))?;
+    writeln!(out, "──────────────────────────────")?;
+    writeln!(
+        out,
+        "Total directories: {}",
+        theme::value(stats.total_entries.to_string())
+    )?;
+    writeln!(
+        out,
+        "Total visits: {}",
+        theme::value(stats.total_visits.to_string())
+    )?;
+
+    if !stats.most_visited.is_empty() {
+        writeln!(out, "\n{}", theme::section("🔥 Most Visited:"))?;
+        for (i, entry) in stats.most_visited.iter().enumerate() {
+            writeln!(
+                out,
+                "  {}. {} ({} visits)",
+                theme::value((i + 1).to_string()),
+                theme::path(&entry.path),
+                theme::value(entry.visits.to_string())
+            )?;
+        }
+    }
+
+    if !stats.recently_visited.is_empty() {
+        writeln!(out, "\n{}", theme::section("⏰ Recently Visited:"))?;
+        for (i, entry) in stats.recently_visited.iter().enumerate() {
+            let time_ago = format_time_ago(&entry.last_access);
+            writeln!(
+                out,
+                "  {}. {} ({})",
+                theme::value((i + 1).to_string()),
+                theme::path(&entry.path),
+                theme::value(&time_ago)
+            )?;
+        }
+    }
+
+    if !bookmarks.is_empty() {
+        writeln!(out, "\n{}", theme::section("🔖 Bookmarks:"))?;
+        for bookmark in bookmarks.iter().take(5) {
+            writeln!(
+                out,
+                "  {} -> {}",
+                theme::name(&bookmark.name),
+                theme::path(&bookmark.path)
+            )?;
+        }
+        if bookmarks.len() > 5 {
+            writeln!(
+                out,
+                "  ... and {} more",
+                theme::value((bookmarks.len() - 5).to_string())
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders `xneo stats --active-days`: a contributions-style grid with a
+/// row per weekday and a column per week, each day shaded by its number of
+/// visits relative to the busiest day, then the totals.
+pub fn render_active_days(days: &[(NaiveDate, u32)]) -> String {
+    let mut out = String::new();
+    write_active_days(&mut out, days).expect("formatting into a String");
+    out
+}
+
+fn write_active_days(out: &mut String, days: &[(NaiveDate, u32)]) -> fmt::Result {
+    writeln!(
+        out,
+        "{}",
+        theme::heading(format!("📅 Activity (last {} days)", days.len()))
+    )?;
+    writeln!(out, "──────────────────────────────")?;
+    let Some(&(first, _)) = days.first() else {
+        return Ok(());
+    };
+
+    let max = days.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    // Weeks start on Monday; blanks pad the first and last columns
+    let lead = first.weekday().num_days_from_monday() as usize;
+    let weeks = (lead + days.len()).div_ceil(7);
+    for (row, label) in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .iter()
+        .enumerate()
+    {
+        write!(out, "{} ", theme::muted(label))?;
+        for week in 0..weeks {
+            let cell = (week * 7 + row)
+                .checked_sub(lead)
+                .and_then(|i| days.get(i))
+                .map_or_else(|| " ".into(), |&(_, count)| shade(count, max));
+            write!(out, " {}", cell)?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "    {} {} {} {} {} {} {}",
+        theme::muted("Less"),
+        shade(0, 4),
+        shade(1, 4),
+        shade(2, 4),
+        shade(3, 4),
+        shade(4, 4),
+        theme::muted("More")
+    )?;
+
+    let total: u32 = days.iter().map(|&(_, count)| count).sum();
+    let active = days.iter().filter(|&&(_, count)| count > 0).count();
+    writeln!(
+        out,
+        "\nTotal visits: {} on {} active days",
+        theme::value(total.to_string()),
+        theme::value(active.to_string())
+    )?;
+    if let Some(&(day, count)) = days
+        .iter()
+        .filter(|&&(_, count)| count > 0)
+        .max_by_key(|&&(day, count)| (count, std::cmp::Reverse(day)))
+    {
+        writeln!(
+            out,
+            "Busiest day: {} ({} visits)",
+            theme::value(day.format("%Y-%m-%d").to_string()),
+            theme::value(count.to_string())
+        )?;
+    }
+    Ok(())
+}
+
+/// A heatmap cell for `count` visits out of a busiest day's `max`: a dot
+/// for none, then four shades by quarter of `max`
+fn shade(count: u32, max: u32) -> colored::ColoredString {
+    if count == 0 || max == 0 {
+        return theme::muted("·");
+    }
+    let block = match (count * 4).div_ceil(max) {
+        0 | 1 => "░",
+        2 => "▒",
+        3 => "▓",
+        _ => "█",
+    };
+    theme::value(block)
+}
+
+/// Renders the `xneo insights` report.
+pub fn render_insights(insights: &Insights) -> String {
+    let mut out = String::new();
+    write_insights(&mut out, insights).expect("formatting into a String");
+    out
+}
+
+fn write_insights(out: &mut String, insights: &Insights) -> fmt::Result {
+    writeln!(out, "{}", theme::heading("💡 xneo Insights"))?;
+    writeln!(out, "──────────────────────────────")?;
+
+    if insights.total_events == 0 {
+        writeln!(out, "No navigation history recorded yet.")?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "Jumps recorded: {}",
+        theme::value(insights.total_events.to_string())
+    )?;
+    if let Some((hour, count)) = insights.busiest_hour {
+        writeln!(
+            out,
+            "Busiest hour: {} ({} jumps)",
+            theme::value(format!("{:02}:00-{:02}:59", hour, hour)),
+            theme::value(count.to_string())
+        )?;
+    }
+    writeln!(
+        out,
+        "Average jumps per session: {}",
+        theme::value(format!("{:.1}", insights.average_session_jumps))
+    )?;
+
+    if !insights.top_project_roots.is_empty() {
+        writeln!(out, "\n{}", theme::section("📁 Top Project Roots:"))?;
+        for (i, (root, count)) in insights.top_project_roots.iter().enumerate() {
+            writeln!(
+                out,
+                "  {}. {} ({} jumps)",
+                theme::value((i + 1).to_string()),
+                theme::path(root),
+                theme::value(count.to_string())
+            )?;
+        }
+    }
+
+    if !insights.abandoned.is_empty() {
+        writeln!(
+            out,
+            "\n{}",
+            theme::section("🕸  Used To Visit, But Stopped:")
+        )?;
+        for (path, count) in insights.abandoned.iter().take(10) {
+            writeln!(
+                out,
+                "  {} ({} visits)",
+                theme::path(path),
+                theme::value(count.to_string())
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders the `xneo clean --report` summary.
+pub fn render_storage_report(report: &StorageReport) -> String {
+    let mut out = String::new();
+    // Writing into a String cannot fail
+    write_storage_report(&mut out, report).expect("formatting into a String");
+    out
+}
+
+fn write_storage_report(out: &mut String, report: &StorageReport) -> fmt::Result {
+    writeln!(out, "{}", theme::heading("💾 xneo Storage"))?;
+    writeln!(out, "──────────────────────────────")?;
+    writeln!(
+        out,
+        "File size: {} ({} pages of {} bytes)",
+        theme::value(format_bytes(report.file_bytes())),
+        theme::value(report.page_count.to_string()),
+        theme::value(report.page_size.to_string())
+    )?;
+    writeln!(
+        out,
+        "Rows: {} ({} directories, {} jumps, {} bookmarks)",
+        theme::value(report.total_rows().to_string()),
+        theme::value(report.dir_rows.to_string()),
+        theme::value(report.event_rows.to_string()),
+        theme::value(report.bookmark_rows.to_string())
+    )?;
+    if let Some(bytes) = report.bytes_per_row() {
+        writeln!(out, "Bytes per row: ~{}", theme::value(bytes.to_string()))?;
+    }
+    writeln!(
+        out,
+        "Reclaimable by vacuum: {} ({} free pages)",
+        theme::value(format_bytes(report.reclaimable_bytes())),
+        theme::value(report.free_pages.to_string())
+    )
+}
+
+/// `bytes` in the largest binary unit that keeps it at least 1
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// `xneo rank-test`: both breakdowns and which path wins by how much
+pub fn render_rank_test(a: &RankBreakdown, b: &RankBreakdown) -> String {
+    let mut out = String::new();
+    // Writing into a String cannot fail
+    write_rank_test(&mut out, a, b).expect("formatting into a String");
+    out
+}
+
+fn write_rank_test(out: &mut String, a: &RankBreakdown, b: &RankBreakdown) -> fmt::Result {
+    for entry in [a, b] {
+        let last_visit = entry
+            .last_access
+            .map_or_else(|| "not tracked".to_string(), |at| format_time_ago(&at));
+        writeln!(out, "{}", theme::path(&entry.path))?;
+        writeln!(
+            out,
+            "  visits {}, last visit {}",
+            theme::value(entry.visits.to_string()),
+            theme::value(&last_visit)
+        )?;
+        writeln!(
+            out,
+            "  frequency {:.4} + recency {:.4} = {}",
+            entry.frequency,
+            entry.recency,
+            theme::value(format!("{:.4}", entry.total))
+        )?;
+    }
+
+    let (winner, loser) = if a.total >= b.total { (a, b) } else { (b, a) };
+    if winner.total == loser.total {
+        writeln!(out, "\n{}", theme::info("Tie"))
+    } else {
+        writeln!(
+            out,
+            "\n{} wins by {:.4}",
+            theme::success(&winner.path),
+            winner.total - loser.total
+        )
+    }
+}
+
+pub fn format_time_ago(datetime: &DateTime<Utc>) -> String {
+    let now = Utc::now();
+    let duration = now.signed_duration_since(*datetime);
+
+    if duration.num_days() > 0 {
+        format!("{} days ago", duration.num_days())
+    } else if duration.num_hours() > 0 {
+        format!("{} hours ago", duration.num_hours())
+    } else if duration.num_minutes() > 0 {
+        format!("{} minutes ago", duration.num_minutes())
+    } else {
+        "just now".to_string()
+    }
+}
@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+/// autojump's data file: `~/Library/autojump/autojump.txt` on macOS,
+/// `~/.local/share/autojump/autojump.txt` (the XDG data dir) elsewhere
+pub fn autojump_data_path() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "macos") {
+        dirs::home_dir().map(|home| home.join("Library"))
+    } else {
+        dirs::data_dir()
+    };
+    dir.map(|dir| dir.join("autojump").join("autojump.txt"))
+}
+
+/// Parses autojump's `weight<TAB>path` lines into `(path, visits)` pairs, the
+/// weight rounded to at least one visit. Malformed lines are skipped.
+pub fn parse_autojump(contents: &str) -> Vec<(String, u32)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (weight, path) = line.split_once('\t')?;
+            let weight: f64 = weight.trim().parse().ok()?;
+            let path = path.trim_end_matches(['\r', '\n']);
+            // `as` saturates, and NaN falls back to the minimum via `max`
+            (!path.is_empty()).then(|| (path.to_string(), weight.round().max(1.0) as u32))
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+use chrono::{DateTime, Duration, Local, Timelike, Utc};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::db::HistoryEvent;
+
+/// Gap between two jumps that starts a new session
+pub const SESSION_GAP_MINUTES: i64 = 30;
+
+/// Directories not visited for this long count as abandoned
+pub const ABANDONED_AFTER_DAYS: i64 = 30;
+
+/// Minimum past visits for an abandoned directory to be reported
+pub const ABANDONED_MIN_VISITS: usize = 3;
+
+/// Components kept when grouping paths that are not under a search root
+const FALLBACK_ROOT_COMPONENTS: usize = 3;
+
+#[derive(Debug)]
+pub struct Insights {
+    pub total_events: usize,
+    /// Local hour of day with the most jumps, and its jump count
+    pub busiest_hour: Option<(u32, usize)>,
+    pub top_project_roots: Vec<(String, usize)>,
+    pub average_session_jumps: f64,
+    pub abandoned: Vec<(String, usize)>,
+}
+
+impl Insights {
+    pub fn from_events(events: &[HistoryEvent], roots: &[PathBuf], now: DateTime<Utc>) -> Self {
+        Insights {
+            total_events: events.len(),
+            busiest_hour: busiest_hour(events),
+            top_project_roots: top_project_roots(events, roots, 5),
+            average_session_jumps: average_session_jumps(events),
+            abandoned: abandoned_dirs(
+                events,
+                now,
+                Duration::days(ABANDONED_AFTER_DAYS),
+                ABANDONED_MIN_VISITS,
+            ),
+        }
+    }
+}
+
+/// The local hour of day (0-23) with the most jumps
+pub fn busiest_hour(events: &[HistoryEvent]) -> Option<(u32, usize)> {
+    let mut counts = [0usize; 24];
+    for event in events {
+        counts[event.timestamp.with_timezone(&Local).hour() as usize] += 1;
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        // Earliest hour wins ties
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)))
+        .map(|(hour, &count)| (hour as u32, count))
+}
+
+/// The project root a path belongs to: the first directory below a search
+/// root, or the leading components of the path when it is under no root.
+pub fn project_root(path: &str, roots: &[PathBuf]) -> String {
+    let path = Path::new(path);
+    for root in roots {
+        if let Ok(rest) = path.strip_prefix(root) {
+            if let Some(first) = rest.components().next() {
+                return root.join(first).to_string_lossy().to_string();
+            }
+            return root.to_string_lossy().to_string();
+        }
+    }
+
+    let mut root = PathBuf::new();
+    let mut kept = 0;
+    for component in path.components() {
+        if kept == FALLBACK_ROOT_COMPONENTS {
+            break;
+        }
+        if matches!(component, Component::Normal(_)) {
+            kept += 1;
+        }
+        root.push(component);
+    }
+    root.to_string_lossy().to_string()
+}
+
+/// The `n` project roots with the most jumps, busiest first
+pub fn top_project_roots(
+    events: &[HistoryEvent],
+    roots: &[PathBuf],
+    n: usize,
+) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for event in events {
+        *counts.entry(project_root(&event.path, roots)).or_default() += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+}
+
+/// Average number of jumps per session, where a session ends after
+/// `SESSION_GAP_MINUTES` without a jump
+pub fn average_session_jumps(events: &[HistoryEvent]) -> f64 {
+    if events.is_empty() {
+        return 0.0;
+    }
+
+    let mut timestamps: Vec<_> = events.iter().map(|e| e.timestamp).collect();
+    timestamps.sort();
+
+    let gap = Duration::minutes(SESSION_GAP_MINUTES);
+    let sessions = 1 + timestamps
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > gap)
+        .count();
+
+    events.len() as f64 / sessions as f64
+}
+
+/// Directories with at least `min_visits` jumps that haven't been visited
+/// within `idle` of `now`, most visited first
+pub fn abandoned_dirs(
+    events: &[HistoryEvent],
+    now: DateTime<Utc>,
+    idle: Duration,
+    min_visits: usize,
+) -> Vec<(String, usize)> {
+    let cutoff = now - idle;
+    let mut visits: HashMap<&str, (usize, DateTime<Utc>)> = HashMap::new();
+    for event in events {
+        let entry = visits
+            .entry(event.path.as_str())
+            .or_insert((0, event.timestamp));
+        entry.0 += 1;
+        entry.1 = entry.1.max(event.timestamp);
+    }
+
+    let mut abandoned: Vec<_> = visits
+        .into_iter()
+        .filter(|(_, (count, last))| *count >= min_visits && *last < cutoff)
+        .map(|(path, (count, _))| (path.to_string(), count))
+        .collect();
+    abandoned.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    abandoned
+}
@@ -24,10 +24,22 @@
 
 pub mod config;
 pub mod db;
+pub mod display;
+pub mod import;
+pub mod insights;
+pub mod nav;
+pub mod picker;
+pub mod project;
 pub mod shell;
+pub mod stream;
+pub mod theme;
 
-pub use config::Config;
-pub use db::{Bookmark, Database, DirEntry, Stats};
+pub use config::{BareInvocation, Config, ConfigReset};
+pub use db::{
+    AddDecision, Bookmark, BookmarkCheck, CleanSummary, Database, DirEntry, HistoryEvent,
+    MoveSummary, QueryFilter, QueryOptions, QueryTrace, RankBreakdown, RootGroup, Shortcut, Stats,
+    StorageReport,
+};
 
 /// Version information
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -38,8 +50,13 @@ pub const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 mod tests {
     use super::*;
     use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
     use tempfile::TempDir;
 
+    // Tests that touch process-wide environment variables must hold this lock
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
     #[test]
     fn test_config_default() {
         let config = Config::default();
@@ -50,6 +67,7 @@ mod tests {
 
     #[test]
     fn test_database_operations() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
         let temp_dir = TempDir::new()?;
         let config = Config::default();
 
@@ -79,32 +97,3061 @@ mod tests {
     }
 
     #[test]
-    fn test_ignored_patterns() {
+    fn test_fuzzy_case_sensitivity() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let keywords = ["myproj".to_string()];
+
+        let mut db = Database::open(&temp_dir.path().join("smart.sqlite"), Config::default())?;
+        db.add("/data/MyProject")?;
+        db.add("/data/myproject-old")?;
+        assert_eq!(db.query(&keywords)?.len(), 2);
+
+        let config = Config {
+            fuzzy_case_sensitive: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("exact.sqlite"), config)?;
+        db.add("/data/MyProject")?;
+        db.add("/data/myproject-old")?;
+        let results = db.query(&keywords)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/data/myproject-old");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_porcelain_summary() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        // Clean database
+        let stale = db.find_stale()?;
+        let summary = CleanSummary {
+            stale: stale.len(),
+            removed: db.purge(&stale)?,
+            merged: 0,
+        };
+        assert_eq!(summary.porcelain(), "stale 0\nremoved 0\nmerged 0");
+        assert_eq!(summary.exit_code(), CleanSummary::EXIT_CLEAN);
+
+        // Dirty database
+        db.add("/nonexistent/xneo/one")?;
+        db.add("/nonexistent/xneo/two")?;
+        db.add(&temp_dir.path().to_string_lossy())?;
+
+        let stale = db.find_stale()?;
+        let left = CleanSummary {
+            stale: stale.len(),
+            removed: 0,
+            merged: 0,
+        };
+        assert_eq!(left.exit_code(), CleanSummary::EXIT_STALE);
+
+        let summary = CleanSummary {
+            stale: stale.len(),
+            removed: db.purge(&stale)?,
+            merged: 0,
+        };
+        assert_eq!(summary.porcelain(), "stale 2\nremoved 2\nmerged 0");
+        assert_eq!(summary.exit_code(), CleanSummary::EXIT_CLEANED);
+
+        // Merging case duplicates changes the database too
+        let merged = CleanSummary {
+            stale: 0,
+            removed: 0,
+            merged: 1,
+        };
+        assert_eq!(merged.exit_code(), CleanSummary::EXIT_CLEANED);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark_aliases() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add_bookmark("work", "/home/user/work")?;
+        assert!(db.add_bookmark_alias("work", "w")?);
+        assert!(!db.add_bookmark_alias("missing", "m")?);
+        assert_eq!(db.get_bookmark("w")?, Some("/home/user/work".to_string()));
+
+        // Updating the canonical bookmark updates the alias
+        db.add_bookmark("work", "/home/user/projects")?;
+        assert_eq!(
+            db.get_bookmark("w")?,
+            Some("/home/user/projects".to_string())
+        );
+
+        let bookmarks = db.get_bookmarks()?;
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].aliases, vec!["w".to_string()]);
+
+        // Removing the canonical bookmark removes its aliases
+        db.remove_bookmark("work")?;
+        assert_eq!(db.get_bookmark("w")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_prefer_cwd() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        // Added in this order, the unrelated match is the more recent one
+        db.add("/work/bigmonorepo/services")?;
+        db.add("/other/project/services")?;
+
+        let keywords = ["services".to_string()];
+        let options = QueryOptions {
+            prefer_cwd: Some("/work/bigmonorepo/app/src".into()),
+            ..QueryOptions::default()
+        };
+        let results = db.query_with(&keywords, &options)?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].path, "/work/bigmonorepo/services");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_database_shared_across_threads() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        let mut writer_db = db.clone();
+        let writer = std::thread::spawn(move || -> anyhow::Result<()> {
+            for i in 0..20 {
+                writer_db.add(&format!("/shared/project{}", i))?;
+            }
+            Ok(())
+        });
+
+        let reader_db = db.clone();
+        let reader = std::thread::spawn(move || -> anyhow::Result<()> {
+            for _ in 0..20 {
+                reader_db.query(&["project".to_string()])?;
+            }
+            Ok(())
+        });
+
+        writer.join().unwrap()?;
+        reader.join().unwrap()?;
+
+        assert_eq!(db.get_stats()?.total_entries, 20);
+        assert_eq!(
+            db.query(&["project7".to_string()])?[0].path,
+            "/shared/project7"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_age_weight() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+
+        for _ in 0..5 {
+            db.add("/old/proj")?;
+        }
+        db.add("/new/proj")?;
+
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path = '/old/proj'",
+            [chrono::Utc::now() - chrono::Duration::days(3)],
+        )?;
+
+        let keywords = ["proj".to_string()];
+        assert_eq!(db.query(&keywords)?[0].path, "/old/proj");
+
+        let options = QueryOptions {
+            age_weight: Some(1.0),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_with(&keywords, &options)?[0].path, "/new/proj");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_stats() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/home/user/alpha")?;
+        db.add("/home/user/alpha")?;
+        db.add("/home/user/beta")?;
+        db.add_bookmark("a", "/home/user/alpha")?;
+
+        let output = display::render_stats(&db.get_stats()?, &db.get_bookmarks()?);
+        assert!(output.contains("xneo Statistics"));
+        assert!(output.contains("Total directories"));
+        assert!(output.contains("Most Visited"));
+        assert!(output.contains("/home/user/alpha"));
+        assert!(output.contains("/home/user/beta"));
+        assert!(output.contains("just now"));
+        assert!(output.contains("Bookmarks"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_skipped_when_env_set() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            skip_if_env_set: vec!["XNEO_TEST_EPHEMERAL_SHELL".to_string()],
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+        std::env::set_var("XNEO_TEST_EPHEMERAL_SHELL", "1");
+        let skipped = db.add("/home/user/rebase");
+        std::env::remove_var("XNEO_TEST_EPHEMERAL_SHELL");
+        skipped?;
+        assert_eq!(db.get_stats()?.total_entries, 0);
+
+        db.add("/home/user/rebase")?;
+        assert_eq!(db.get_stats()?.total_entries, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_pagination() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for i in 0..30 {
+            db.add(&format!("/projects/p{:02}/page", i))?;
+        }
+
+        let keywords = ["page".to_string()];
+        let mut seen = std::collections::HashSet::new();
+        for page in 0..3 {
+            let options = QueryOptions {
+                offset: page * 10,
+                limit: Some(10),
+                ..QueryOptions::default()
+            };
+            let results = db.query_with(&keywords, &options)?;
+            assert_eq!(results.len(), 10);
+            seen.extend(results.into_iter().map(|e| e.path));
+        }
+        assert_eq!(seen.len(), 30);
+
+        // Offsetting past the end is empty, not an error
+        let options = QueryOptions {
+            offset: 30,
+            limit: Some(10),
+            ..QueryOptions::default()
+        };
+        assert!(db.query_with(&keywords, &options)?.is_empty());
+
+        // Without a limit, the default cap applies
+        assert_eq!(db.query(&keywords)?.len(), db::DEFAULT_QUERY_LIMIT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_literal_bookmark_expansion() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add_bookmark("work", "$HOME/work")?;
+        let stored = db.get_bookmark("work")?.unwrap();
+        assert_eq!(stored, "$HOME/work");
+
+        let home = std::env::var("HOME")?;
+        assert_eq!(nav::expand_path(&stored), format!("{}/work", home));
+
+        // Undefined variables leave the path untouched
+        assert_eq!(
+            nav::expand_path("$XNEO_TEST_UNDEFINED_VAR/work"),
+            "$XNEO_TEST_UNDEFINED_VAR/work"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_track_depth_from_root() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            search_roots: vec!["/work".to_string()],
+            max_track_depth_from_root: Some(2),
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+        db.add("/work/app/gen/out/debug/obj")?;
+        db.add("/work/app")?;
+        db.add("/elsewhere/a/b/c/d/e")?;
+
+        let stats = db.get_stats()?;
+        let mut paths: Vec<_> = stats.most_visited.iter().map(|e| e.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/elsewhere/a/b/c/d/e", "/work/app"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_existing_absolute_path() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        let target = temp_dir.path().join("somewhere");
+        fs::create_dir(&target)?;
+        let target = target.to_string_lossy().to_string();
+        let keywords = [target.clone()];
+
+        // Untracked but existing: returned as-is
+        let results = db.query(&keywords)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, target);
+        assert_eq!(results[0].visits, 0);
+
+        // Tracked: returned with its stored visits
+        db.add(&target)?;
+        let results = db.query(&keywords)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].visits, 1);
+
+        Ok(())
+    }
+
+    fn event_at(path: &str, timestamp: chrono::DateTime<chrono::Utc>) -> HistoryEvent {
+        HistoryEvent {
+            path: path.to_string(),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_insights_busiest_hour() {
+        use chrono::TimeZone;
+        let at = |h, m| {
+            chrono::Local
+                .with_ymd_and_hms(2026, 3, 2, h, m, 0)
+                .unwrap()
+                .with_timezone(&chrono::Utc)
+        };
+        let events = vec![
+            event_at("/a", at(9, 0)),
+            event_at("/a", at(14, 5)),
+            event_at("/b", at(14, 40)),
+            event_at("/c", at(22, 0)),
+        ];
+
+        assert_eq!(insights::busiest_hour(&events), Some((14, 2)));
+        assert_eq!(insights::busiest_hour(&[]), None);
+    }
+
+    #[test]
+    fn test_insights_top_project_roots() {
+        let now = chrono::Utc::now();
+        let roots = vec![std::path::PathBuf::from("/work")];
+        let events = vec![
+            event_at("/work/alpha/src", now),
+            event_at("/work/alpha/tests", now),
+            event_at("/work/beta", now),
+            event_at("/opt/tools/bin/extra", now),
+        ];
+
+        let top = insights::top_project_roots(&events, &roots, 2);
+        assert_eq!(
+            top,
+            vec![
+                ("/work/alpha".to_string(), 2),
+                ("/opt/tools/bin".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insights_average_session_jumps() {
+        let start = chrono::Utc::now() - chrono::Duration::days(1);
+        let minutes = |m| start + chrono::Duration::minutes(m);
+        // Two sessions: three jumps, then (after a long gap) one jump
+        let events = vec![
+            event_at("/a", minutes(0)),
+            event_at("/b", minutes(10)),
+            event_at("/c", minutes(25)),
+            event_at("/a", minutes(300)),
+        ];
+
+        assert_eq!(insights::average_session_jumps(&events), 2.0);
+        assert_eq!(insights::average_session_jumps(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_insights_abandoned_dirs() {
+        let now = chrono::Utc::now();
+        let days_ago = |d| now - chrono::Duration::days(d);
+        let events = vec![
+            // Frequently visited, but not in the last 30 days
+            event_at("/old/project", days_ago(60)),
+            event_at("/old/project", days_ago(55)),
+            event_at("/old/project", days_ago(50)),
+            // Frequently visited and still active
+            event_at("/active", days_ago(60)),
+            event_at("/active", days_ago(40)),
+            event_at("/active", days_ago(1)),
+            // Old but rarely visited
+            event_at("/rare", days_ago(80)),
+        ];
+
+        let abandoned = insights::abandoned_dirs(&events, now, chrono::Duration::days(30), 3);
+        assert_eq!(abandoned, vec![("/old/project".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_add_dry_run_decision() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        let decision = db.add_decision("/home/user/project");
+        assert_eq!(decision, AddDecision::Record);
+        assert_eq!(decision.to_string(), "record");
+
+        let decision = db.add_decision("/home/user/project/node_modules");
+        assert_eq!(decision, AddDecision::Ignored);
+        assert_eq!(decision.to_string(), "skip: matches an ignored pattern");
+
+        // Deciding never writes
+        assert_eq!(db.get_stats()?.total_entries, 0);
+
+        db.add("/home/user/project")?;
+        assert_eq!(db.get_stats()?.total_entries, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_and_double_slashes_are_normalized() -> anyhow::Result<()> {
+        assert_eq!(nav::normalize_path("/home/user/proj/"), "/home/user/proj");
+        assert_eq!(nav::normalize_path("/home//user///proj"), "/home/user/proj");
+        assert_eq!(nav::normalize_path("/"), "/");
+        assert_eq!(nav::normalize_path("//"), "/");
+        assert_eq!(nav::normalize_path(r"C:\"), "C:/");
+        assert_eq!(nav::normalize_path(r"\\server\share\"), "//server/share");
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add("/home/user/proj")?;
+        db.add("/home/user/proj/")?;
+        db.add("/home//user/proj")?;
+        let stats = db.get_stats()?;
+        assert_eq!(stats.total_entries, 1);
+        assert_eq!(stats.most_visited[0].path, "/home/user/proj");
+        assert_eq!(stats.most_visited[0].visits, 3);
+
+        // Lookups with a trailing slash find the same row
+        let results = db.query(&["user/proj/".to_string()])?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/home/user/proj");
+
+        db.add_bookmark("proj", "/home/user//proj/")?;
+        assert_eq!(db.get_bookmark("proj")?.as_deref(), Some("/home/user/proj"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_paths_are_normalized() -> anyhow::Result<()> {
+        assert_eq!(
+            nav::normalize_separators(r"C:\Users\me\project"),
+            "C:/Users/me/project"
+        );
+        assert_eq!(
+            nav::normalize_separators(r"\\server\share\dir"),
+            "//server/share/dir"
+        );
+        if cfg!(unix) {
+            // Backslashes are ordinary file name characters on Unix
+            assert_eq!(nav::normalize_separators(r"/tmp/a\b"), r"/tmp/a\b");
+        }
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add(r"C:\Users\me\project")?;
+        db.add(r"C:\Users\me\project\target\debug")?;
+
+        let results = db.query(&["project".to_string()])?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "C:/Users/me/project");
+
         let config = Config::default();
+        assert!(config.is_ignored(r"C:\Users\me\project\target"));
+        assert!(config.is_ignored(r"C:\Users\me\project\target\debug"));
+        assert!(!config.is_ignored(r"C:\Users\me\project"));
 
-        // Should ignore common build/cache directories
-        assert!(config.is_ignored("/project/node_modules/package"));
-        assert!(config.is_ignored("/project/.git/hooks"));
-        assert!(config.is_ignored("/rust/project/target/debug"));
+        Ok(())
+    }
 
-        // Should not ignore regular directories
-        assert!(!config.is_ignored("/home/user/projects"));
-        assert!(!config.is_ignored("/usr/local/bin"));
+    #[test]
+    fn test_bookmark_allow_missing_is_unverified() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        let mounted = temp_dir.path().to_string_lossy().to_string();
+        // A missing path needs --allow-missing
+        assert!(db
+            .add_bookmark_checked("nas", "/mnt/xneo-unmounted-share", false)
+            .is_err());
+        assert_eq!(db.get_bookmark("nas")?, None);
+        assert!(!db.add_bookmark_checked("nas", "/mnt/xneo-unmounted-share", true)?);
+        assert!(db.add_bookmark_checked("here", &mounted, false)?);
+
+        assert_eq!(
+            db.get_bookmark("nas")?.as_deref(),
+            Some("/mnt/xneo-unmounted-share")
+        );
+
+        let bookmarks = db.get_bookmarks()?;
+        let nas = bookmarks.iter().find(|b| b.name == "nas").unwrap();
+        let here = bookmarks.iter().find(|b| b.name == "here").unwrap();
+        assert!(!nas.is_verified());
+        assert!(here.is_verified());
+
+        Ok(())
     }
 
     #[test]
-    fn test_shell_scripts_exist() {
-        // Ensure all shell scripts are available
-        assert!(!shell::FISH_INIT_SCRIPT.is_empty());
-        assert!(!shell::BASH_INIT_SCRIPT.is_empty());
-        assert!(!shell::ZSH_INIT_SCRIPT.is_empty());
-        assert!(!shell::POWERSHELL_INIT_SCRIPT.is_empty());
+    fn test_bare_invocation_keeps_home_for_scripts() {
+        // `cd (command xneo)` captures stdout, so it is never a terminal there
+        let config = Config::default();
+        assert_eq!(config.bare_invocation, BareInvocation::Auto);
+        assert!(config.bare_invocation.prints_home(false));
+        assert!(!config.bare_invocation.prints_home(true));
 
-        // Check that scripts contain expected functionality
-        assert!(shell::FISH_INIT_SCRIPT.contains("function x"));
-        assert!(shell::BASH_INIT_SCRIPT.contains("x() {"));
-        assert!(shell::ZSH_INIT_SCRIPT.contains("x() {"));
-        assert!(shell::POWERSHELL_INIT_SCRIPT.contains("function x"));
+        assert!(BareInvocation::Home.prints_home(true));
+        assert!(!BareInvocation::Help.prints_home(false));
+
+        let parsed: Config = serde_json::from_str(r#"{"bare_invocation": "home"}"#).unwrap();
+        assert_eq!(parsed.bare_invocation, BareInvocation::Home);
     }
-}
 
+    #[test]
+    fn test_map_dir_row() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        db.add("/srv/app")?;
+        db.add("/srv/app")?;
+
+        let conn = rusqlite::Connection::open(&db_path)?;
+        let entry = conn.query_row(
+            &format!(
+                "SELECT {} FROM dirs WHERE path = '/srv/app'",
+                db::DIR_COLUMNS
+            ),
+            [],
+            db::map_dir_row,
+        )?;
+
+        assert_eq!(entry.path, "/srv/app");
+        assert_eq!(entry.visits, 2);
+        assert!((chrono::Utc::now() - entry.last_access).num_seconds() < 60);
+        // The persisted rank is set on every visit
+        assert!(entry.rank > 0.0);
+        let queried = &db.query(&["/srv/app".to_string()])?[0];
+        assert!((queried.rank - entry.rank).abs() < 1e-3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_recent_finds_neglected_dirs() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+
+        for _ in 0..25 {
+            db.add("/old/important")?;
+        }
+        db.add("/new/casual")?;
+        db.add("/old/minor")?;
+
+        let now = chrono::Utc::now();
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path LIKE '/old/%'",
+            [now - chrono::Duration::days(60)],
+        )?;
+
+        let neglected = db.neglected(now - chrono::Duration::days(30), 10)?;
+        let paths: Vec<_> = neglected.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/old/important", "/old/minor"]);
+        assert_eq!(neglected[0].visits, 25);
+
+        assert_eq!(db.neglected(now - chrono::Duration::days(30), 1)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_schema_is_read_only() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+
+        {
+            let mut db = Database::open(&db_path, Config::default())?;
+            db.add("/home/user/project")?;
+            assert!(db.schema_warning().is_none());
+        }
+
+        let conn = rusqlite::Connection::open(&db_path)?;
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        assert_eq!(version, db::SCHEMA_VERSION);
+        conn.pragma_update(None, "user_version", db::SCHEMA_VERSION + 1)?;
+
+        let mut db = Database::open(&db_path, Config::default())?;
+        let warning = db.schema_warning().expect("newer schema should warn");
+        assert!(warning.contains(&(db::SCHEMA_VERSION + 1).to_string()));
+
+        // Reads still work; writes are refused or skipped
+        assert_eq!(db.query(&["project".to_string()])?.len(), 1);
+        assert_eq!(
+            db.add_decision("/home/user/other"),
+            AddDecision::NewerSchema
+        );
+        db.add("/home/user/other")?;
+        assert!(db.add_bookmark("p", "/home/user/project").is_err());
+        assert!(db.purge(&["/home/user/project".to_string()]).is_err());
+        assert_eq!(db.query(&["other".to_string()])?.len(), 0);
+
+        // The stamp is left alone
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        assert_eq!(version, db::SCHEMA_VERSION + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_restores_purged_entries() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+
+        for _ in 0..3 {
+            db.add("/gone/alpha")?;
+        }
+        db.add("/gone/beta")?;
+        db.add("/kept")?;
+
+        let old = chrono::Utc::now() - chrono::Duration::days(12);
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path = '/gone/alpha'",
+            [old],
+        )?;
+
+        let snapshot = || -> rusqlite::Result<Vec<(String, u32, String)>> {
+            let mut stmt = conn.prepare(
+                "SELECT path, visits_total, last_access FROM dirs
+                 WHERE path LIKE '/gone/%' ORDER BY path",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        };
+        let before = snapshot()?;
+
+        let stale = vec!["/gone/alpha".to_string(), "/gone/beta".to_string()];
+        assert_eq!(db.purge(&stale)?, 2);
+        assert!(db.query(&["gone".to_string()])?.is_empty());
+
+        assert_eq!(db.undo()?, 2);
+        let after = snapshot()?;
+        assert_eq!(before, after);
+        assert_eq!(before[0].1, 3);
+
+        // The undo buffer only holds one operation
+        assert_eq!(db.undo()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forgiving_bookmark_lookup() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("exact.sqlite"), Config::default())?;
+        db.add_bookmark("work ", "/home/user/work")?;
+
+        // Whitespace is always trimmed, but case is exact by default
+        assert_eq!(
+            db.get_bookmark("  work ")?.as_deref(),
+            Some("/home/user/work")
+        );
+        assert_eq!(db.get_bookmark("Work")?, None);
+        db.add_bookmark("WORK", "/srv/WORK")?;
+        drop(db);
+
+        // Spellings stored before the flag was turned on stay distinct
+        let config = Config {
+            bookmark_case_insensitive: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("exact.sqlite"), config)?;
+        db.add_bookmark("Notes", "/home/user/notes")?;
+
+        assert_eq!(
+            db.get_bookmark("notes ")?.as_deref(),
+            Some("/home/user/notes")
+        );
+        // An exact-case name wins over a case-folded one
+        assert_eq!(db.get_bookmark("WORK")?.as_deref(), Some("/srv/WORK"));
+        assert_eq!(db.get_bookmark("work")?.as_deref(), Some("/home/user/work"));
+        assert_eq!(db.bookmarks_with_prefix("no")?, ["/home/user/notes"]);
+
+        // Removing folds case too, still preferring the exact spelling
+        db.add_bookmark_alias("Notes", "N")?;
+        assert!(db.remove_bookmark("NOTES")?);
+        assert_eq!(db.get_bookmark("n")?, None);
+        assert!(db.remove_bookmark("WORK")?);
+        assert_eq!(db.get_bookmark("Work")?.as_deref(), Some("/home/user/work"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark_prefix_matching() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add_bookmark("work", "/home/user/work")?;
+        db.add_bookmark("work2", "/home/user/work2")?;
+        db.add_bookmark("workx", "/srv/workx")?;
+        db.add_bookmark("other", "/tmp/other")?;
+        db.add_bookmark_alias("workx", "wox")?;
+
+        assert_eq!(
+            db.bookmarks_with_prefix("wo")?,
+            ["/home/user/work", "/home/user/work2", "/srv/workx"]
+        );
+        assert_eq!(db.bookmarks_with_prefix("other")?, ["/tmp/other"]);
+        assert!(db.bookmarks_with_prefix("z")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_scoped_to_bookmark() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add_bookmark("work", "/home/user/work")?;
+        db.add("/home/user/work/shop/api")?;
+        for _ in 0..5 {
+            db.add("/home/user/personal/api")?;
+        }
+
+        // Without the bookmark the busier directory wins
+        assert_eq!(
+            db.query(&["api".to_string()])?[0].path,
+            "/home/user/personal/api"
+        );
+
+        let scoped = db.query(&["work".to_string(), "api".to_string()])?;
+        let paths: Vec<&str> = scoped.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/home/user/work/shop/api"]);
+
+        // Nothing under the bookmark matches
+        assert!(db
+            .query(&["work".to_string(), "personal".to_string()])?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffered_adds_flush_on_query() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let config = Config {
+            buffer_adds: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&db_path, config)?;
+
+        db.add("/home/user/buffered")?;
+        db.add("/home/user/buffered")?;
+        Database::buffer_visit(&db_path, "/home/user/hooked", chrono::Utc::now())?;
+
+        // Nothing reaches SQLite until something reads
+        assert_eq!(db.get_stats()?.total_entries, 0);
+        let log = fs::read_to_string(temp_dir.path().join("db.pending"))?;
+        assert_eq!(log.lines().count(), 3);
+
+        let results = db.query(&["buffered".to_string()])?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].visits, 2);
+        assert_eq!(db.get_stats()?.total_entries, 2);
+        assert!(!temp_dir.path().join("db.pending").exists());
+
+        // Already applied; a second flush finds nothing
+        assert_eq!(db.flush_pending()?, 0);
+
+        // While another process flushes, the log is left to it
+        Database::buffer_visit(&db_path, "/home/user/hooked", chrono::Utc::now())?;
+        let held = fs::File::create(db::flush_lock_path(&temp_dir.path().join("db.pending")))?;
+        held.lock()?;
+        assert_eq!(db.flush_pending()?, 0);
+        held.unlock()?;
+        assert_eq!(db.flush_pending()?, 1);
+
+        // A first buffered add creates the database directory
+        let fresh = temp_dir.path().join("fresh").join("db.sqlite");
+        Database::buffer_visit(&fresh, "/home/user/new", chrono::Utc::now())?;
+        assert!(fresh.with_extension("pending").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_case_duplicates() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        // As recorded on a case-insensitive filesystem
+        db.add("/Users/me/Project")?;
+        db.add("/Users/me/Project")?;
+        db.add("/Users/me/project")?;
+        db.add("/Users/me/other")?;
+
+        // Only where the filesystem holding them ignores case
+        assert_eq!(db.count_case_duplicates(|_, _| false)?, 0);
+        assert_eq!(db.merge_case_duplicates(|_, _| false)?, 0);
+        let mut compared = Vec::new();
+        assert_eq!(
+            db.count_case_duplicates(|kept, other| {
+                compared.push((kept.to_path_buf(), other.to_path_buf()));
+                true
+            })?,
+            1
+        );
+        assert_eq!(
+            compared,
+            [(
+                PathBuf::from("/Users/me/Project"),
+                PathBuf::from("/Users/me/project")
+            )]
+        );
+        assert_eq!(db.merge_case_duplicates(|_, _| true)?, 1);
+
+        let stats = db.get_stats()?;
+        assert_eq!(stats.total_entries, 2);
+        assert_eq!(stats.most_visited[0].path, "/Users/me/Project");
+        assert_eq!(stats.most_visited[0].visits, 3);
+        assert!(db
+            .history_events(None, None)?
+            .iter()
+            .all(|event| event.path != "/Users/me/project"));
+
+        // Nothing left to merge
+        assert_eq!(db.merge_case_duplicates(|_, _| true)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_bookmarks_blends_results() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add("/home/user/project")?;
+        db.add("/home/user/notes")?;
+        db.add_bookmark("proj", "/home/user/project/")?;
+        db.add_bookmark("etc", "/etc/nginx")?;
+
+        let results = db.query(&["/home/user/".to_string()])?;
+        let lines = display::blend_bookmarks(&results, &db.get_bookmarks()?, "{path}");
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&"/home/user/project\t[proj]".to_string()));
+        assert!(lines.contains(&"/home/user/notes".to_string()));
+        assert_eq!(lines[2], "/etc/nginx\t[etc]");
+
+        // The annotation never ends up in the path handed to cd
+        assert_eq!(
+            display::strip_bookmark_annotation("/home/user/project\t[proj]"),
+            "/home/user/project"
+        );
+        assert_eq!(
+            display::strip_bookmark_annotation("/home/user/notes"),
+            "/home/user/notes"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge_in_batches() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            max_entries: 5000,
+            purge_batch_size: 300,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+        for i in 0..2000 {
+            db.add(&format!("/gone/dir{}", i))?;
+        }
+        db.add("/home/user/kept")?;
+
+        let stale = db.find_stale()?;
+        assert_eq!(stale.len(), 2001);
+        let doomed: Vec<String> = stale
+            .into_iter()
+            .filter(|path| path.starts_with("/gone/"))
+            .collect();
+
+        let mut reports = Vec::new();
+        let removed = db.purge_with_progress(&doomed, |handled| reports.push(handled))?;
+
+        assert_eq!(removed, 2000);
+        assert_eq!(reports.len(), 7);
+        assert_eq!(reports[0], 300);
+        assert_eq!(reports.last(), Some(&2000));
+        assert_eq!(db.get_stats()?.total_entries, 1);
+
+        // The whole purge can still be undone
+        assert_eq!(db.undo()?, 2000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stdin_query_streams_result_blocks() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/home/user/alpha")?;
+        db.add("/home/user/beta")?;
+
+        let input = "alpha\nzzzzqqq\nbeta\n".as_bytes();
+        let mut output = Vec::new();
+        let answered = stream::serve_queries(
+            &db,
+            input,
+            &mut output,
+            &QueryOptions::default(),
+            display::DEFAULT_ENTRY_FORMAT,
+        )?;
+        assert_eq!(answered, 3);
+
+        let output = String::from_utf8(output)?;
+        let blocks: Vec<&str> = output.split_terminator("\n\n").collect();
+        assert_eq!(output.matches("\n\n").count(), 2);
+        assert_eq!(blocks[0], "/home/user/alpha");
+        assert!(output.ends_with("/home/user/beta\n\n"));
+        // The query with no results yields an empty block
+        assert!(output.contains("/home/user/alpha\n\n\n/home/user/beta"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_keywords_skip_fuzzy_matching() -> anyhow::Result<()> {
+        let query = |min_fuzzy_query_len: usize| -> anyhow::Result<Vec<String>> {
+            let temp_dir = TempDir::new()?;
+            let config = Config {
+                min_fuzzy_query_len,
+                substring_at_word_start: false,
+                ..Config::default()
+            };
+            let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+            db.add("/srv/xq")?;
+            db.add("/srv/xq")?;
+            db.add("/srv/qx")?;
+            let results = db.query(&["q".to_string()])?;
+            Ok(results.into_iter().map(|entry| entry.path).collect())
+        };
+
+        // Fuzzy scoring favors the match at a word boundary...
+        assert_eq!(query(1)?, ["/srv/qx", "/srv/xq"]);
+        // ...while the substring stage ranks by frecency alone
+        assert_eq!(query(2)?, ["/srv/xq", "/srv/qx"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_prefix_rewrites_dirs_and_bookmarks() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add("/home/user/work/api")?;
+        db.add("/home/user/work/api")?;
+        db.add("/home/user/workshop")?;
+        db.add("/home/user/projects/api")?;
+        db.add_bookmark("api", "/home/user/work/api")?;
+        db.add_bookmark("shop", "/home/user/workshop")?;
+        // Stored with --literal: matched by its expansion, kept in its form
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var("XNEO_TEST_MOVE_USER", "/home/user");
+        db.add_bookmark("web", "$XNEO_TEST_MOVE_USER/work/web")?;
+
+        let summary = db.move_prefix("/home/user/work/", "/home/user/projects")?;
+        assert_eq!(
+            summary,
+            MoveSummary {
+                dirs: 1,
+                bookmarks: 2
+            }
+        );
+        assert_eq!(
+            db.get_bookmark("web")?.as_deref(),
+            Some("$XNEO_TEST_MOVE_USER/projects/web")
+        );
+
+        // The moved row merged into the one already at the new path
+        let stats = db.get_stats()?;
+        assert_eq!(stats.total_entries, 2);
+        assert_eq!(stats.most_visited[0].path, "/home/user/projects/api");
+        assert_eq!(stats.most_visited[0].visits, 3);
+
+        assert_eq!(
+            db.get_bookmark("api")?.as_deref(),
+            Some("/home/user/projects/api")
+        );
+        // Only whole path components match the prefix
+        assert_eq!(
+            db.get_bookmark("shop")?.as_deref(),
+            Some("/home/user/workshop")
+        );
+        assert!(db
+            .history_events(None, None)?
+            .iter()
+            .all(|event| !event.path.starts_with("/home/user/work/")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_substring_fallback_matches_at_word_start() -> anyhow::Result<()> {
+        let query = |substring_at_word_start: bool| -> anyhow::Result<Vec<String>> {
+            let temp_dir = TempDir::new()?;
+            let config = Config {
+                enable_fuzzy_matching: false,
+                substring_at_word_start,
+                ..Config::default()
+            };
+            let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+            db.add("/x/app/src")?;
+            db.add("/x/my-app")?;
+            db.add("/home/scrapper/data")?;
+            let results = db.query(&["ap".to_string()])?;
+            let mut paths: Vec<String> = results.into_iter().map(|entry| entry.path).collect();
+            paths.sort();
+            Ok(paths)
+        };
+
+        assert_eq!(query(true)?, ["/x/app/src", "/x/my-app"]);
+        assert_eq!(
+            query(false)?,
+            ["/home/scrapper/data", "/x/app/src", "/x/my-app"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_csv_quoting() -> anyhow::Result<()> {
+        assert_eq!(display::csv_field("/home/user/plain"), "/home/user/plain");
+        assert_eq!(display::csv_field("/data/a,b"), "\"/data/a,b\"");
+        assert_eq!(
+            display::csv_field("/data/say \"hi\""),
+            "\"/data/say \"\"hi\"\"\""
+        );
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/data/a,b")?;
+        db.add("/data/a,b")?;
+        db.add("/data/plain")?;
+
+        let csv = display::render_csv(&db.top(usize::MAX)?);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines[0], "path,visits,last_access,rank");
+        assert!(lines[1].starts_with("\"/data/a,b\",2,"));
+        assert!(lines[2].starts_with("/data/plain,1,"));
+        assert_eq!(lines.len(), 4);
+
+        assert_eq!(display::render_csv(&db.top(1)?).lines().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeated_query_uses_cache() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/home/user/alpha")?;
+
+        let keywords = ["alpha".to_string()];
+        let first = db.query(&keywords)?;
+        assert_eq!(db.scans(), 1);
+        let second = db.query(&keywords)?;
+        assert_eq!(db.scans(), 1);
+        assert_eq!(first[0].path, second[0].path);
+
+        // Different options are cached separately
+        let options = QueryOptions {
+            any: true,
+            ..QueryOptions::default()
+        };
+        db.query_with(&keywords, &options)?;
+        assert_eq!(db.scans(), 2);
+
+        // Any write invalidates the cache
+        db.add("/home/user/alpha")?;
+        assert_eq!(db.query(&keywords)?[0].visits, 2);
+        assert_eq!(db.scans(), 3);
+
+        // Clones share the cache
+        let clone = db.clone();
+        clone.query(&keywords)?;
+        assert_eq!(db.scans(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_descendant() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add("/w/app/services/api")?;
+        db.add("/w/app/legacy/api")?;
+        db.add("/w/app/legacy/api")?;
+        db.add("/w/other/api")?;
+        db.add("/w/other/api")?;
+        db.add("/w/other/api")?;
+        db.add("/w/app")?;
+
+        let cwd = Path::new("/w/app");
+        let found = db.find_descendant("api", cwd)?.map(|entry| entry.path);
+        assert_eq!(found.as_deref(), Some("/w/app/legacy/api"));
+
+        // cwd itself and siblings sharing a name prefix don't count
+        assert!(db.find_descendant("app", Path::new("/w/app"))?.is_none());
+        assert!(db.find_descendant("api", Path::new("/w/ap"))?.is_none());
+        assert!(db.find_descendant("missing", cwd)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blank_keywords_match_nothing() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/home/user/project")?;
+
+        assert!(db.query(&["".to_string()])?.is_empty());
+        assert!(db.query(&["   ".to_string(), "\t".to_string()])?.is_empty());
+
+        // Surrounding whitespace is ignored
+        let results = db.query(&[" project ".to_string(), "".to_string()])?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/home/user/project");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_path_components() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            min_path_components: 2,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+        assert_eq!(db.add_decision("/"), AddDecision::TooShallow);
+        assert_eq!(db.add_decision("/tmp"), AddDecision::TooShallow);
+        assert_eq!(db.add_decision(r"C:\Users"), AddDecision::TooShallow);
+        assert_eq!(db.add_decision("/home/user"), AddDecision::Record);
+
+        db.add("/")?;
+        db.add("/tmp")?;
+        db.add("/home/user")?;
+        assert_eq!(db.get_stats()?.total_entries, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_select_if_dominant() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        for _ in 0..200 {
+            db.add("/work/app/src")?;
+        }
+        db.add("/play/src")?;
+        for _ in 0..30 {
+            db.add("/lib/one/util")?;
+        }
+        for _ in 0..28 {
+            db.add("/lib/two/util")?;
+        }
+
+        let options = QueryOptions {
+            auto_select_if_dominant: Some(2.0),
+            ..QueryOptions::default()
+        };
+
+        // A clear favourite skips the picker
+        let results = db.query_with(&["src".to_string()], &options)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/work/app/src");
+
+        // A close race still offers every candidate
+        let results = db.query_with(&["util".to_string()], &options)?;
+        assert_eq!(results.len(), 2);
+        assert!(!db::is_dominant(&results, 2.0));
+
+        // Disabled by default
+        assert_eq!(db.query(&["src".to_string()])?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_keyword_mode() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        for _ in 0..5 {
+            db.add("/code/foo")?;
+        }
+        for _ in 0..3 {
+            db.add("/code/bar")?;
+        }
+        db.add("/code/foo/bar")?;
+        db.add("/code/baz")?;
+
+        let options = QueryOptions {
+            any: true,
+            ..QueryOptions::default()
+        };
+        let keywords = ["foo".to_string(), "bar".to_string()];
+        let paths: Vec<_> = db
+            .query_with(&keywords, &options)?
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+
+        // Matching both keywords beats frecency; then the busier directory wins
+        assert_eq!(paths, ["/code/foo/bar", "/code/foo", "/code/bar"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_negation_keywords() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add("/work/api")?;
+        db.add("/work/api/test")?;
+        db.add("/work/api-tests")?;
+        db.add("/work/-api-scratch")?;
+
+        let keywords = ["api".to_string(), "-test".to_string()];
+        let results: Vec<_> = db.query(&keywords)?.into_iter().map(|e| e.path).collect();
+        assert!(results.contains(&"/work/api".to_string()));
+        assert!(results.iter().all(|path| !path.contains("test")));
+
+        // After `--`, a leading dash is part of the search term
+        let keywords = ["--".to_string(), "-api-scratch".to_string()];
+        let results = db.query(&keywords)?;
+        assert_eq!(results[0].path, "/work/-api-scratch");
+
+        // Only exclusions: nothing to search for
+        assert!(db.query(&["-test".to_string()])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_interactive_progress_is_hidden() {
+        // --quiet always disables the progress UI
+        assert!(!display::is_interactive(true));
+
+        let spinner = display::spinner(false, "Checking directories...");
+        spinner.tick();
+        assert!(spinner.is_hidden());
+        spinner.finish_and_clear();
+
+        let bar = display::progress_bar(false, 3, "Cleaning...");
+        bar.inc(3);
+        assert!(bar.is_hidden());
+        bar.finish_with_message("Done!");
+    }
+
+    #[test]
+    fn test_query_format_template() {
+        let entry = DirEntry {
+            path: "/home/user/project".to_string(),
+            last_access: chrono::DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+            visits: 7,
+            rank: 1.23456,
+        };
+
+        assert_eq!(
+            display::format_entry("{rank} {visits} {path}", &entry),
+            "1.235 7 /home/user/project"
+        );
+        assert_eq!(
+            display::format_entry("{path}|{last_access}", &entry),
+            "/home/user/project|2024-05-01T12:00:00+00:00"
+        );
+        assert_eq!(
+            display::format_entry(display::DEFAULT_ENTRY_FORMAT, &entry),
+            "/home/user/project"
+        );
+    }
+
+    #[test]
+    fn test_suggest_describe_format() {
+        assert_eq!(
+            display::format_suggestion("/home/user/work/api/src"),
+            "src\t/home/user/work/api/src"
+        );
+        assert_eq!(display::format_suggestion("/"), "/\t/");
+
+        let line = display::format_suggestion("/srv/app");
+        let columns: Vec<&str> = line.split('\t').collect();
+        assert_eq!(columns, ["app", "/srv/app"]);
+    }
+
+    #[test]
+    fn test_persisted_rank_matches_fresh_rank() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        // Without fuzzy matching the substring stage orders purely by rank
+        let config = Config {
+            enable_fuzzy_matching: false,
+            ..Config::default()
+        };
+        let mut db = Database::open(&db_path, config)?;
+
+        for (i, path) in ["/r/alpha", "/r/beta", "/r/gamma", "/r/delta"]
+            .iter()
+            .enumerate()
+        {
+            for _ in 0..=i * 2 {
+                db.add(path)?;
+            }
+        }
+
+        // Age some rows so that recency differs from visit order
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path = '/r/delta'",
+            [chrono::Utc::now() - chrono::Duration::days(30)],
+        )?;
+        db.refresh_ranks()?;
+
+        let persisted = db.top(10)?;
+        let fresh = db.query(&["/r/".to_string()])?;
+        assert_eq!(persisted.len(), 4);
+
+        let persisted_order: Vec<_> = persisted.iter().map(|e| e.path.clone()).collect();
+        let fresh_order: Vec<_> = fresh.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(persisted_order, fresh_order);
+
+        for entry in &persisted {
+            let fresh_entry = fresh.iter().find(|e| e.path == entry.path).unwrap();
+            assert!((entry.rank - fresh_entry.rank).abs() < 1e-3);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignored_patterns() {
+        let config = Config::default();
+
+        // Should ignore common build/cache directories
+        assert!(config.is_ignored("/project/node_modules/package"));
+        assert!(config.is_ignored("/project/.git/hooks"));
+        assert!(config.is_ignored("/rust/project/target/debug"));
+
+        // Should not ignore regular directories
+        assert!(!config.is_ignored("/home/user/projects"));
+        assert!(!config.is_ignored("/usr/local/bin"));
+    }
+
+    #[test]
+    fn test_anchored_ignore_patterns() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            r#"{ "ignored_patterns": ["/mnt/data/**", "/srv/*", "**/target"] }"#,
+        )?;
+
+        std::env::set_var("XNEO_CONFIG_PATH", &config_path);
+        let config = Config::load();
+        std::env::remove_var("XNEO_CONFIG_PATH");
+        let config = config?;
+
+        // Absolute-anchored pattern only matches under that prefix
+        assert!(config.is_ignored("/mnt/data"));
+        assert!(config.is_ignored("/mnt/data/photos/2024"));
+        assert!(!config.is_ignored("/home/user/mnt/data/photos"));
+        assert!(!config.is_ignored("/mnt/database"));
+
+        // A single `*` does not cross directory separators
+        assert!(config.is_ignored("/srv/www"));
+        assert!(!config.is_ignored("/srv/www/site"));
+
+        // Relative-style patterns still match anywhere
+        assert!(config.is_ignored("/home/user/project/target"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_fragments_are_merged() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            r#"{ "ignored_patterns": ["**/target"], "max_entries": 100 }"#,
+        )?;
+        fs::create_dir(temp_dir.path().join("conf.d"))?;
+        fs::write(
+            temp_dir.path().join("conf.d/10-work.json"),
+            r#"{ "ignored_patterns": ["/mnt/scratch/**"], "max_entries": 200 }"#,
+        )?;
+        fs::write(
+            temp_dir.path().join("conf.d/20-later.json"),
+            r#"{ "max_entries": 300 }"#,
+        )?;
+        fs::write(temp_dir.path().join("conf.d/notes.txt"), "not json")?;
+
+        std::env::set_var("XNEO_CONFIG_PATH", &config_path);
+        let config = Config::load();
+        std::env::remove_var("XNEO_CONFIG_PATH");
+        let config = config?;
+
+        assert_eq!(config.ignored_patterns, ["**/target", "/mnt/scratch/**"]);
+        assert!(config.is_ignored("/mnt/scratch/tmp"));
+        assert!(config.is_ignored("/home/user/project/target"));
+        // Later files win for plain values
+        assert_eq!(config.max_entries, 300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_dots() {
+        let cwd = std::path::Path::new("/home/user/work/project");
+
+        assert_eq!(
+            nav::resolve_dots("...", cwd),
+            Some(std::path::PathBuf::from("/home/user"))
+        );
+        assert_eq!(
+            nav::resolve_dots("..", cwd),
+            Some(std::path::PathBuf::from("/home/user/work"))
+        );
+        // Going past the root stops at the root
+        assert_eq!(
+            nav::resolve_dots("........", cwd),
+            Some(std::path::PathBuf::from("/"))
+        );
+        // Not a dots-only keyword
+        assert_eq!(nav::resolve_dots("..foo", cwd), None);
+        assert_eq!(nav::resolve_dots(".", cwd), None);
+    }
+
+    #[test]
+    fn test_find_ancestor_depth_and_farthest() {
+        let cwd = Path::new("/w/app/src/app/lib/deep");
+
+        assert_eq!(
+            nav::find_ancestor("app", cwd, None, false),
+            Some(PathBuf::from("/w/app/src/app"))
+        );
+        assert_eq!(
+            nav::find_ancestor("app", cwd, None, true),
+            Some(PathBuf::from("/w/app"))
+        );
+
+        // `app` is 2 levels up (nearest) and 4 levels up (farthest)
+        assert_eq!(nav::find_ancestor("app", cwd, Some(1), false), None);
+        assert_eq!(
+            nav::find_ancestor("app", cwd, Some(3), true),
+            Some(PathBuf::from("/w/app/src/app"))
+        );
+        assert_eq!(
+            nav::find_ancestor("app", cwd, Some(4), true),
+            Some(PathBuf::from("/w/app"))
+        );
+
+        // Depth 0 only considers the current directory
+        assert_eq!(
+            nav::find_ancestor("deep", cwd, Some(0), false),
+            Some(cwd.to_path_buf())
+        );
+        assert_eq!(nav::find_ancestor("missing", cwd, None, true), None);
+    }
+
+    #[test]
+    fn test_path_env_overrides() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("custom/config.json");
+        let db_path = temp_dir.path().join("custom/db.sqlite");
+
+        std::env::set_var("XNEO_CONFIG_PATH", &config_path);
+        std::env::set_var("XNEO_DB_PATH", &db_path);
+
+        assert_eq!(Config::config_path()?, config_path);
+        assert_eq!(Database::db_path()?, db_path);
+
+        // The database is created at the overridden location
+        Database::new(Config::default())?;
+        assert!(fs::metadata(&db_path).is_ok());
+
+        std::env::remove_var("XNEO_CONFIG_PATH");
+        std::env::remove_var("XNEO_DB_PATH");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cdpath_fallback() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let projects = temp_dir.path().join("projects");
+        fs::create_dir_all(projects.join("widget"))?;
+
+        let config = Config {
+            use_cdpath: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+        db.add("/somewhere/else")?;
+
+        let cdpath = std::env::join_paths([temp_dir.path().join("missing"), projects.clone()])?;
+        std::env::set_var("CDPATH", &cdpath);
+        let results = db.query(&["widget".to_string()])?;
+        let disabled = Database::open(&temp_dir.path().join("db2.sqlite"), Config::default())?
+            .query(&["widget".to_string()])?;
+        std::env::remove_var("CDPATH");
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].path,
+            projects.join("widget").to_string_lossy().to_string()
+        );
+        assert!(disabled.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_reset_backs_up_and_confirms() -> anyhow::Result<()> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("config.json");
+        std::env::set_var("XNEO_CONFIG_PATH", &config_path);
+
+        let tuned = r#"{"max_entries": 42, "fzf_options": "--tuned"}"#;
+        fs::write(&config_path, tuned)?;
+
+        // Declined: nothing is written
+        assert_eq!(Config::reset(|| Ok(false))?, ConfigReset::Declined);
+        assert_eq!(fs::read_to_string(&config_path)?, tuned);
+        assert!(!Config::backup_path()?.exists());
+
+        // Confirmed (--yes): old file is backed up and defaults are written
+        let backup = temp_dir.path().join("config.json.bak");
+        assert_eq!(
+            Config::reset(|| Ok(true))?,
+            ConfigReset::Done {
+                backup: Some(backup.clone())
+            }
+        );
+        assert_eq!(Config::backup_path()?, backup);
+        assert_eq!(fs::read_to_string(Config::backup_path()?)?, tuned);
+        let reset = Config::load()?;
+        assert_eq!(reset.max_entries, Config::default().max_entries);
+        assert_eq!(reset.fzf_options, Config::default().fzf_options);
+
+        // Without a config file there is nothing to back up
+        fs::remove_file(&config_path)?;
+        assert_eq!(
+            Config::reset(|| Ok(true))?,
+            ConfigReset::Done { backup: None }
+        );
+
+        std::env::remove_var("XNEO_CONFIG_PATH");
+        Ok(())
+    }
+
+    #[test]
+    fn test_picker_fzf_args() {
+        let parsed: Config = serde_json::from_str(
+            r#"{"fzf_options": "--reverse --border", "picker_height": "50%",
+                "picker_preview_command": "tree -L 1 {}"}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.picker_height.as_deref(), Some("50%"));
+        assert_eq!(
+            parsed.picker_preview_command.as_deref(),
+            Some("tree -L 1 {}")
+        );
+
+        assert_eq!(
+            picker::fzf_args(&parsed, Some(15)),
+            [
+                "--reverse",
+                "--border",
+                "--height=50%",
+                "--preview=tree -L 1 {}",
+                "--preview-window=down:15",
+                "--prompt=Select directory: ",
+            ]
+        );
+
+        // Defaults keep fzf_options as is and add no preview
+        let config = Config::default();
+        let args = picker::fzf_args(&config, None);
+        assert!(args.starts_with(&["--height=40%".to_string()]));
+        assert!(!args.iter().any(|arg| arg.starts_with("--preview")));
+
+        // --preview-lines alone falls back to the default preview
+        let args = picker::fzf_args(&config, Some(5));
+        assert!(args.contains(&format!("--preview={}", picker::DEFAULT_PREVIEW_COMMAND)));
+    }
+
+    #[test]
+    fn test_pick_falls_back_to_numbered_prompt_without_fzf() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let marker = temp_dir.path().join("hint-shown");
+        let candidates = vec!["/srv/api".to_string(), "/srv/web".to_string()];
+        let pick = |answer: &str| -> anyhow::Result<(Option<String>, String)> {
+            let mut prompt_out = Vec::new();
+            let choice = picker::pick_with(
+                "xneo-test-no-such-picker",
+                &[],
+                &candidates,
+                &mut answer.as_bytes(),
+                &mut prompt_out,
+                Some(&marker),
+            )?;
+            Ok((choice, String::from_utf8(prompt_out)?))
+        };
+
+        let (choice, shown) = pick("2\n")?;
+        assert_eq!(choice.as_deref(), Some("/srv/web"));
+        assert!(shown.contains("Hint:"));
+        assert!(shown.contains("  1) /srv/api\n  2) /srv/web\n"));
+
+        // The hint is shown only once; bad answers cancel
+        let (choice, shown) = pick("3\n")?;
+        assert_eq!(choice, None);
+        assert!(!shown.contains("Hint:"));
+        assert_eq!(pick("")?.0, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_theme_none_is_uncolored() {
+        use theme::{Role, Theme};
+
+        assert!(!Theme::Dark.paint("/home/user", Role::Path).is_plain());
+        for role in [
+            Role::Heading,
+            Role::Path,
+            Role::Success,
+            Role::Error,
+            Role::Muted,
+        ] {
+            let painted = Theme::None.paint("/home/user", role);
+            assert!(painted.is_plain());
+            assert_eq!(painted.to_string(), "/home/user");
+        }
+
+        let parsed: Config = serde_json::from_str(r#"{"theme": "none"}"#).unwrap();
+        assert_eq!(parsed.theme, Theme::None);
+        assert_eq!(Theme::Light.resolve(), Theme::Light);
+    }
+
+    #[test]
+    fn test_version_output() {
+        let config_path = Path::new("/cfg/xneo/config.json");
+        let db_path = Path::new("/data/xneo/db.sqlite");
+
+        let short = display::render_version(false, config_path, db_path);
+        assert_eq!(short, format!("{} {}\n", NAME, VERSION));
+
+        let verbose = display::render_version(true, config_path, db_path);
+        assert!(verbose.starts_with(&short));
+        assert!(verbose.contains(VERSION));
+        assert!(verbose.contains("/cfg/xneo/config.json"));
+        assert!(verbose.contains("/data/xneo/db.sqlite"));
+        assert!(verbose.contains(rusqlite::version()));
+        assert!(verbose.contains(&format!("Schema version: {}", db::SCHEMA_VERSION)));
+    }
+
+    #[test]
+    fn test_post_jump_command_in_init_scripts() {
+        for script in [
+            shell::FISH_INIT_SCRIPT,
+            shell::BASH_INIT_SCRIPT,
+            shell::ZSH_INIT_SCRIPT,
+        ] {
+            assert!(script.contains(shell::POST_JUMP_MARKER));
+
+            let hooked = shell::with_post_jump(script, Some("ls -a"));
+            assert!(hooked.contains("ls -a"));
+            assert!(!hooked.contains(shell::POST_JUMP_MARKER));
+            // Every jump in `x` goes through the hooked helper
+            assert!(!hooked.contains("\n            cd "));
+
+            assert_eq!(shell::with_post_jump(script, None), script);
+            assert_eq!(shell::with_post_jump(script, Some("  ")), script);
+        }
+    }
+
+    #[test]
+    fn test_fish_report_errors_logs_hook_failures() {
+        let log = Path::new("/home/me/.cache/xneo/add.log");
+        let script = shell::with_add_log(shell::FISH_INIT_SCRIPT, log);
+        assert!(script.contains(r#"command xneo add "$PWD" 2>>'/home/me/.cache/xneo/add.log' &"#));
+        assert!(!script.contains(r#"command xneo add "$PWD" &"#));
+
+        let quoted = shell::with_add_log(shell::FISH_INIT_SCRIPT, Path::new("/tmp/it's/add.log"));
+        assert!(quoted.contains(r"2>>'/tmp/it\'s/add.log'"));
+    }
+
+    #[test]
+    fn test_shell_scripts_exist() {
+        // Ensure all shell scripts are available
+        assert!(!shell::FISH_INIT_SCRIPT.is_empty());
+        assert!(!shell::BASH_INIT_SCRIPT.is_empty());
+        assert!(!shell::ZSH_INIT_SCRIPT.is_empty());
+        assert!(!shell::POWERSHELL_INIT_SCRIPT.is_empty());
+
+        // Check that scripts contain expected functionality
+        assert!(shell::FISH_INIT_SCRIPT.contains("function x"));
+        assert!(shell::BASH_INIT_SCRIPT.contains("x() {"));
+        assert!(shell::ZSH_INIT_SCRIPT.contains("x() {"));
+        assert!(shell::POWERSHELL_INIT_SCRIPT.contains("function x"));
+    }
+
+    #[test]
+    fn test_init_script_for_every_supported_shell() {
+        for name in ["fish", "bash", "zsh", "powershell"] {
+            let script = shell::init_script(name).unwrap();
+            assert!(script.contains(shell::POST_JUMP_MARKER), "{name}");
+            assert!(shell::rc_snippet(name).is_some(), "{name}");
+        }
+        let powershell = shell::init_script("powershell").unwrap();
+        assert!(powershell.contains("function global:prompt"));
+        assert!(powershell.contains("xneo add"));
+        assert!(shell::init_script("tcsh").is_none());
+    }
+
+    #[test]
+    fn test_bookmark_usage_tracking() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+
+        db.add_bookmark("work", "/home/user/work")?;
+        db.add_bookmark("notes", "/home/user/notes")?;
+        db.add_bookmark_alias("work", "w")?;
+
+        // Plain lookups don't count; jumps through an alias count for the target
+        db.get_bookmark("work")?;
+        assert_eq!(
+            db.resolve_bookmark("work")?.as_deref(),
+            Some("/home/user/work")
+        );
+        assert_eq!(
+            db.resolve_bookmark("w")?.as_deref(),
+            Some("/home/user/work")
+        );
+        assert_eq!(db.resolve_bookmark("missing")?, None);
+
+        let bookmarks = db.get_bookmarks()?;
+        let work = bookmarks.iter().find(|b| b.name == "work").unwrap();
+        let notes = bookmarks.iter().find(|b| b.name == "notes").unwrap();
+        assert_eq!(work.use_count, 2);
+        assert!(work.last_used.is_some());
+        assert_eq!(notes.use_count, 0);
+        assert_eq!(notes.last_used, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keywords_from_file_prints_one_line_per_query() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/home/user/alpha")?;
+        db.add("/home/user/beta")?;
+
+        let queries = temp_dir.path().join("queries.txt");
+        std::fs::write(&queries, "alpha\n\nbeta\nzzzzqqq\n")?;
+        let mut output = Vec::new();
+        let answered = stream::resolve_queries(
+            &db,
+            std::io::BufReader::new(std::fs::File::open(&queries)?),
+            &mut output,
+            &QueryOptions::default(),
+            display::DEFAULT_ENTRY_FORMAT,
+        )?;
+        assert_eq!(answered, 3);
+
+        let output = String::from_utf8(output)?;
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "alpha\t/home/user/alpha",
+                "beta\t/home/user/beta",
+                "zzzzqqq\t"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_dir_replaces_home_when_it_exists() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let workspace = temp_dir.path().join("workspace");
+        std::fs::create_dir(&workspace)?;
+
+        let config = Config {
+            default_dir: Some(workspace.display().to_string()),
+            ..Config::default()
+        };
+        assert_eq!(config.landing_dir(), Some(workspace));
+
+        let missing = Config {
+            default_dir: Some(temp_dir.path().join("gone").display().to_string()),
+            ..Config::default()
+        };
+        assert_eq!(missing.landing_dir(), dirs::home_dir());
+        assert_eq!(Config::default().landing_dir(), dirs::home_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fuzzy_prefers_exact_case_basename() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/src/Core/Config")?;
+        db.add("/src/Core/config")?;
+        db.add("/src/Core/config")?;
+
+        // The exact-case sibling wins over the more frecent one
+        let results = db.query(&["Config".to_string()])?;
+        assert_eq!(results[0].path, "/src/Core/Config");
+
+        // Past the exact stage, names containing the keyword get the bonus;
+        // both fuzzy-match `Configs` (the lowercase one through `Core`)
+        db.add("/src/Core/MyConfigs")?;
+        db.add("/src/Core/myconfigs")?;
+        db.add("/src/Core/myconfigs")?;
+        let results = db.query(&["Configs".to_string()])?;
+        assert_eq!(results[0].path, "/src/Core/MyConfigs");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_autojump_data_file() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        let project = temp_dir.path().join("project");
+        let notes = temp_dir.path().join("notes");
+        let target = project.join("target");
+        fs::create_dir_all(&target)?;
+        fs::create_dir(&notes)?;
+
+        let data = format!(
+            "22.4\t{}\n0.3\t{}\n5.0\t{}\n9.0\t{}\nnot a line\n",
+            project.display(),
+            notes.display(),
+            target.display(),
+            temp_dir.path().join("gone").display()
+        );
+        let visits = import::parse_autojump(&data);
+        assert_eq!(visits.len(), 4);
+
+        // The ignored `target` and the missing directory are skipped
+        assert_eq!(db.import_visits(&visits)?, 2);
+        let entries = db.top(usize::MAX)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, project.display().to_string());
+        assert_eq!(entries[0].visits, 22);
+        assert_eq!(entries[1].path, notes.display().to_string());
+        assert_eq!(entries[1].visits, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_reports_winning_stage() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/srv/projects/xneo")?;
+        db.add("/srv/projects/xnote")?;
+
+        let trace = db.explain(&["xn".to_string()], &QueryOptions::default())?;
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&trace)?)?;
+        assert_eq!(json["stage"], "fuzzy");
+        let stages: Vec<&str> = json["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stage| stage["stage"].as_str().unwrap())
+            .collect();
+        assert_eq!(stages, vec!["exact", "dir_name", "fuzzy"]);
+        assert_eq!(json["top"].as_array().unwrap().len(), 2);
+
+        let trace = db.explain(&["xneo".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.stage, Some("exact"));
+        assert_eq!(trace.top[0].path, "/srv/projects/xneo");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_reports_winning_bookmark() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for _ in 0..5 {
+            db.add("/srv/projects/foo")?;
+        }
+        db.add_bookmark("foo", "/srv/elsewhere")?;
+        db.add_bookmark_alias("foo", "f")?;
+
+        // The bookmark wins even over a frecent directory with the same name
+        let trace = db.explain(&["foo".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.bookmark.as_deref(), Some("foo"));
+        assert_eq!(trace.top[0].path, "/srv/projects/foo");
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&trace)?)?;
+        assert_eq!(json["bookmark"], "foo");
+        assert_eq!(json["shortcut"], "bookmark");
+
+        // Aliases report the bookmark they stand for
+        let trace = db.explain(&["f".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.bookmark.as_deref(), Some("foo"));
+        assert_eq!(trace.shortcut, Some("bookmark"));
+
+        // Explaining doesn't count as using the bookmark
+        assert_eq!(db.get_bookmarks()?[0].use_count, 0);
+
+        // Partial names offer the bookmarks they prefix
+        let trace = db.explain(&["fo".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.bookmark, None);
+        assert_eq!(trace.shortcut, Some("bookmark_prefix"));
+
+        // Dots go up from the current directory
+        let trace = db.explain(&["..".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.shortcut, Some("dots"));
+
+        // Bookmarks only take over single-keyword queries
+        let trace = db.explain(
+            &["foo".to_string(), "srv".to_string()],
+            &QueryOptions::default(),
+        )?;
+        assert_eq!(trace.bookmark, None);
+        assert_eq!(trace.shortcut, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_hidden_skips_dotfile_directories() -> anyhow::Result<()> {
+        for ignore_hidden in [true, false] {
+            let temp_dir = TempDir::new()?;
+            let config = Config {
+                ignore_hidden,
+                ..Config::default()
+            };
+            let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+            db.add("/home/user/.config/foo")?;
+            db.add("/home/user/projects/foo")?;
+            let recorded = db.top(usize::MAX)?.len();
+            if ignore_hidden {
+                assert_eq!(
+                    db.add_decision("/home/user/.config/foo"),
+                    AddDecision::Hidden
+                );
+                assert_eq!(recorded, 1);
+            } else {
+                assert_eq!(
+                    db.add_decision("/home/user/.config/foo"),
+                    AddDecision::Record
+                );
+                assert_eq!(recorded, 2);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_aliases_match_both_names() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = fs::canonicalize(temp_dir.path())?;
+        let real = root.join("real-project");
+        let link = root.join("shortcut");
+        fs::create_dir(&real)?;
+        std::os::unix::fs::symlink(&real, &link)?;
+
+        let config = Config {
+            record_symlink_aliases: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&root.join("db.sqlite"), config)?;
+        db.add(&link.display().to_string())?;
+        db.add(&real.display().to_string())?;
+
+        // One row with both visits, found under either name
+        let entries = db.top(usize::MAX)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].visits, 2);
+        for keyword in ["shortcut", "real-project"] {
+            let results = db.query(&[keyword.to_string()])?;
+            assert_eq!(results.len(), 1, "{keyword}");
+            assert_eq!(results[0].path, real.display().to_string());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_top_sets_list_lengths() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for i in 0..30 {
+            db.add(&format!("/home/user/dir{i}"))?;
+        }
+
+        let stats = db.get_stats_top(3)?;
+        assert_eq!(stats.most_visited.len(), 3);
+        assert_eq!(stats.recently_visited.len(), 3);
+
+        let stats = db.get_stats_top(25)?;
+        assert_eq!(stats.most_visited.len(), 25);
+        assert_eq!(stats.recently_visited.len(), 25);
+        assert_eq!(stats.total_entries, 30);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_ties_are_ordered_by_path() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        for path in ["/srv/c", "/srv/a", "/srv/b"] {
+            db.add(path)?;
+        }
+        // Same visits and the same last access for all three
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute("UPDATE dirs SET last_access = ?1", [chrono::Utc::now()])?;
+
+        let paths = |entries: &[DirEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.path.clone()).collect()
+        };
+        let stats = db.get_stats()?;
+        assert_eq!(paths(&stats.most_visited), ["/srv/a", "/srv/b", "/srv/c"]);
+        assert_eq!(
+            paths(&stats.recently_visited),
+            ["/srv/a", "/srv/b", "/srv/c"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_diff_lists_only_changed_fields() -> anyhow::Result<()> {
+        assert!(Config::default().diff_from_default()?.is_empty());
+
+        let config = Config {
+            max_entries: 250,
+            ..Config::default()
+        };
+        let diff = config.diff_from_default()?;
+        assert_eq!(diff.len(), 1);
+        let (field, default, current) = &diff[0];
+        assert_eq!(field, "max_entries");
+        assert_eq!(*default, serde_json::json!(1000));
+        assert_eq!(*current, serde_json::json!(250));
+        assert!(!diff.iter().any(|(field, ..)| field == "fzf_options"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_aliases_rewrite_keywords() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            query_aliases: [("cfg".to_string(), "config".to_string())].into(),
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+        db.add("/home/user/config")?;
+        db.add("/home/user/cfgx")?;
+
+        let results = db.query(&["cfg".to_string()])?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/home/user/config");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_empty_detects_empty_directories() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        let empty = temp_dir.path().join("empty");
+        let full = temp_dir.path().join("full");
+        fs::create_dir(&empty)?;
+        fs::create_dir(&full)?;
+        fs::write(full.join("notes.txt"), "x")?;
+        db.add(&empty.display().to_string())?;
+        db.add(&full.display().to_string())?;
+        db.add("/definitely/missing/dir")?;
+
+        // Missing directories are `find_stale`'s business
+        let empty_paths = db.find_empty()?;
+        assert_eq!(empty_paths, vec![empty.display().to_string()]);
+        assert_eq!(db.purge(&empty_paths)?, 1);
+        assert_eq!(db.get_stats()?.total_entries, 2);
+
+        Ok(())
+    }
+
+    /// Collects log messages from every thread, for the logging test
+    struct CaptureLogger(Mutex<Vec<String>>);
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Debug
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let mut messages = self.0.lock().unwrap();
+                messages.push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE_LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+    #[test]
+    fn test_query_emits_debug_log() -> anyhow::Result<()> {
+        log::set_logger(&CAPTURE_LOGGER).expect("only this test installs a logger");
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/srv/logged-project")?;
+        db.query(&["logged-project".to_string()])?;
+
+        let messages = CAPTURE_LOGGER.0.lock().unwrap();
+        assert!(messages.iter().any(
+            |message| message.contains("\"logged-project\"") && message.contains("exact stage")
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_home_dir_not_recorded_by_default() -> anyhow::Result<()> {
+        let Some(home) = dirs::home_dir() else {
+            return Ok(());
+        };
+        let home = home.display().to_string();
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        assert_eq!(db.add_decision(&home), AddDecision::HomeDir);
+        db.add(&home)?;
+        assert_eq!(db.get_stats()?.total_entries, 0);
+
+        let config = Config {
+            ignore_home_dir: false,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("other.sqlite"), config)?;
+        db.add(&home)?;
+        assert_eq!(db.get_stats()?.total_entries, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_breakdown_picks_the_winner() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        for _ in 0..10 {
+            db.add("/srv/frequent")?;
+        }
+        db.add("/srv/recent")?;
+
+        let now = chrono::Utc::now();
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path = '/srv/frequent'",
+            [now - chrono::Duration::days(2)],
+        )?;
+
+        let frequent = db.rank_breakdown("/srv/frequent", &now)?;
+        let recent = db.rank_breakdown("/srv/recent", &now)?;
+        assert_eq!(frequent.visits, 10);
+        assert!((frequent.frequency + frequent.recency - frequent.total).abs() < 1e-9);
+        assert!(recent.recency > frequent.recency);
+        // Ten visits outweigh being visited just now
+        assert!(frequent.total > recent.total);
+        let report = display::render_rank_test(&recent, &frequent);
+        let verdict = report.lines().last().unwrap();
+        assert!(verdict.contains("/srv/frequent") && verdict.contains("wins by"));
+
+        let missing = db.rank_breakdown("/srv/unknown", &now)?;
+        assert_eq!((missing.visits, missing.total), (0, 0.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_huge_keyword_is_capped() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for i in 0..200 {
+            db.add(&format!("/srv/projects/app{i}/src/components"))?;
+        }
+
+        let blob = "ab".repeat(5_000);
+        let started = std::time::Instant::now();
+        let results = db.query(std::slice::from_ref(&blob))?;
+        assert!(results.is_empty());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+        let trace = db.explain(&[blob, "more".to_string()], &QueryOptions::default())?;
+        assert_eq!(trace.keywords.len(), 1);
+        assert_eq!(trace.keywords[0].chars().count(), 256);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_window_is_half_open() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        for path in ["/d1", "/d2", "/d3", "/d4"] {
+            db.add(&format!("/srv{path}"))?;
+        }
+
+        // One event per day at noon, 4 to 1 days ago
+        let day = |n: i64| {
+            chrono::Utc::now()
+                .date_naive()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_utc()
+                - chrono::Duration::days(n)
+        };
+        let conn = rusqlite::Connection::open(&db_path)?;
+        for (n, path) in [
+            (4, "/srv/d1"),
+            (3, "/srv/d2"),
+            (2, "/srv/d3"),
+            (1, "/srv/d4"),
+        ] {
+            conn.execute(
+                "UPDATE events SET timestamp = ?1 WHERE path = ?2",
+                rusqlite::params![day(n), path],
+            )?;
+        }
+
+        let paths = |events: Vec<HistoryEvent>| -> Vec<String> {
+            events.into_iter().map(|event| event.path).collect()
+        };
+        // `since` is inclusive, `until` exclusive
+        let window = db.history_events(Some(day(3)), Some(day(1)))?;
+        assert_eq!(paths(window), vec!["/srv/d2", "/srv/d3"]);
+        assert_eq!(
+            paths(db.history_events(None, Some(day(3)))?),
+            vec!["/srv/d1"]
+        );
+        assert_eq!(db.history_events(Some(day(2)), None)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rc_snippet_for_each_shell() {
+        assert_eq!(
+            shell::rc_snippet("fish"),
+            Some(("~/.config/fish/config.fish", "xneo init fish | source"))
+        );
+        assert_eq!(
+            shell::rc_snippet("bash"),
+            Some(("~/.bashrc", r#"eval "$(xneo init bash)""#))
+        );
+        assert_eq!(
+            shell::rc_snippet("zsh"),
+            Some(("~/.zshrc", r#"eval "$(xneo init zsh)""#))
+        );
+        assert_eq!(shell::rc_snippet("tcsh"), None);
+    }
+
+    #[test]
+    fn test_query_order_is_deterministic() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        // Equal visits and near-equal ranks: only the path can break ties
+        for i in 0..12 {
+            db.add(&format!("/srv/team{i}/lib/core"))?;
+        }
+
+        let paths = |db: &Database, keyword: &str| -> anyhow::Result<Vec<String>> {
+            let results = db.query(&[keyword.to_string()])?;
+            Ok(results.into_iter().map(|entry| entry.path).collect())
+        };
+        for keyword in ["lib", "core", "tea"] {
+            let first = paths(&db, keyword)?;
+            assert_eq!(first.len(), 12, "{keyword}");
+            for _ in 0..50 {
+                // Fresh handles skip the per-handle result cache
+                let db = Database::open(&db_path, Config::default())?;
+                assert_eq!(paths(&db, keyword)?, first, "{keyword}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forget_prefix_lists_and_deletes() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/tmp/worktrees/feature-a")?;
+        db.add("/tmp/worktrees/feature-b/src")?;
+        db.add("/tmp/worktrees-archive/old")?;
+        db.add("/home/user/project")?;
+
+        // The dry-run listing: a plain prefix, sorted
+        let listed = db.paths_with_prefix("/tmp/worktrees/")?;
+        assert_eq!(
+            listed,
+            vec!["/tmp/worktrees/feature-a", "/tmp/worktrees/feature-b/src"]
+        );
+        assert_eq!(db.paths_with_prefix("/tmp/worktrees")?.len(), 3);
+        assert!(db.paths_with_prefix("")?.is_empty());
+
+        assert_eq!(db.purge(&listed)?, 2);
+        assert_eq!(db.get_stats()?.total_entries, 2);
+        assert!(db.paths_with_prefix("/tmp/worktrees/")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_scripts_read_fzf_options_from_config() {
+        for script in [
+            shell::FISH_INIT_SCRIPT,
+            shell::BASH_INIT_SCRIPT,
+            shell::ZSH_INIT_SCRIPT,
+        ] {
+            assert!(script.contains("command xneo config get fzf_options"));
+            assert!(!script.contains("--height=40%"));
+        }
+    }
+
+    #[test]
+    fn test_query_with_matches_returns_highlight_indices() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/srv/dotfiles")?;
+        db.add("/srv/projects/web")?;
+
+        let highlighted = |path: &str, indices: &[usize]| -> String {
+            let chars: Vec<char> = path.chars().collect();
+            indices.iter().map(|&i| chars[i]).collect()
+        };
+
+        // Fuzzy: the picked characters spell the keyword
+        let results = db.query_with_matches(&["dtf".to_string()], 10)?;
+        assert_eq!(results.len(), 1);
+        let (entry, indices) = &results[0];
+        assert_eq!(entry.path, "/srv/dotfiles");
+        assert_eq!(highlighted(&entry.path, indices), "dtf");
+
+        // Exact match: the keyword's own range
+        let results = db.query_with_matches(&["web".to_string()], 10)?;
+        let (entry, indices) = &results[0];
+        assert_eq!(indices, &vec![14, 15, 16]);
+        assert_eq!(highlighted(&entry.path, indices), "web");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_at_records_a_past_visit() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        let now = chrono::Utc::now();
+        let three_days_ago = now - chrono::Duration::days(3);
+
+        db.add_at("/srv/replayed", three_days_ago)?;
+        let breakdown = db.rank_breakdown("/srv/replayed", &now)?;
+        assert_eq!(breakdown.last_access, Some(three_days_ago));
+        let events = db.history_events(None, None)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, three_days_ago);
+
+        // An older replayed visit counts but doesn't move last_access back
+        db.add_at("/srv/replayed", now - chrono::Duration::days(5))?;
+        let breakdown = db.rank_breakdown("/srv/replayed", &now)?;
+        assert_eq!(breakdown.visits, 2);
+        assert_eq!(breakdown.last_access, Some(three_days_ago));
+
+        // Visits can't be recorded ahead of time
+        let tomorrow = chrono::Utc::now() + chrono::Duration::days(1);
+        assert!(db.add_at("/srv/replayed", tomorrow).is_err());
+        assert_eq!(db.rank_breakdown("/srv/replayed", &now)?.visits, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_report_is_consistent() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        db.add("/srv/api")?;
+        db.add("/srv/api")?;
+        db.add("/srv/web")?;
+        db.add_bookmark("api", "/srv/api")?;
+
+        let report = db.storage_report()?;
+        assert_eq!(report.dir_rows, 2);
+        assert_eq!(report.event_rows, 3);
+        assert_eq!(report.bookmark_rows, 1);
+        assert_eq!(report.total_rows(), 6);
+        assert!(report.page_size > 0 && report.page_count > 0);
+        assert!(report.free_pages <= report.page_count);
+        assert_eq!(report.file_bytes(), fs::metadata(&db_path)?.len());
+        assert_eq!(report.bytes_per_row(), Some(report.file_bytes() / 6));
+        assert!(report.reclaimable_bytes() < report.file_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark_check_detects_and_fixes() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let target = temp_dir.path().join("work");
+        fs::create_dir(&target)?;
+        let target = target.to_string_lossy().into_owned();
+
+        let mut db = Database::open(&db_path, Config::default())?;
+        db.add_bookmark("work", &target)?;
+        db.add_bookmark("job", &target)?;
+        db.add_bookmark("nas", "/mnt/nas-that-does-not-exist")?;
+        assert!(db.check_bookmarks()?.invalid.is_empty());
+
+        // A row `add_bookmark` would never write, e.g. from a manual edit
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "INSERT INTO bookmarks (name, path) VALUES ('  ', ?1)",
+            [&target],
+        )?;
+        conn.execute(
+            "INSERT INTO bookmark_aliases (alias, target) VALUES ('blank', '  ')",
+            [],
+        )?;
+
+        let check = db.check_bookmarks()?;
+        assert_eq!(check.invalid, ["  "]);
+        assert_eq!(check.missing, ["nas"]);
+        assert_eq!(
+            check.duplicates,
+            [(target.clone(), vec!["job".to_string(), "work".to_string()])]
+        );
+
+        assert_eq!(db.fix_bookmarks()?, 1);
+        let check = db.check_bookmarks()?;
+        assert!(check.invalid.is_empty());
+        assert_eq!(check.missing, ["nas"]);
+        assert_eq!(db.get_bookmark("blank")?, None);
+        assert_eq!(db.get_bookmarks()?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_filter_min_visits_and_max_age() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let mut db = Database::open(&db_path, Config::default())?;
+        let visits = [
+            ("/srv/api-busy-fresh", 5),
+            ("/srv/api-busy-stale", 5),
+            ("/srv/api-rare-fresh", 1),
+            ("/srv/web-busy-fresh", 5),
+        ];
+        for (path, count) in visits {
+            for _ in 0..count {
+                db.add(path)?;
+            }
+        }
+        let conn = rusqlite::Connection::open(&db_path)?;
+        conn.execute(
+            "UPDATE dirs SET last_access = ?1 WHERE path = '/srv/api-busy-stale'",
+            [chrono::Utc::now() - chrono::Duration::days(30)],
+        )?;
+
+        let options = QueryOptions {
+            filter: QueryFilter {
+                min_visits: Some(3),
+                max_age: Some(chrono::Duration::days(7)),
+                project_type: None,
+            },
+            ..QueryOptions::default()
+        };
+        let results = db.query_with(&["api".to_string()], &options)?;
+        let paths: Vec<&str> = results.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["/srv/api-busy-fresh"]);
+
+        // Each threshold on its own
+        let only_visits = QueryFilter {
+            min_visits: Some(3),
+            max_age: None,
+            project_type: None,
+        };
+        let now = chrono::Utc::now();
+        let all = db.query(&["api".to_string()])?;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all.iter().filter(|e| only_visits.keeps(e, &now)).count(), 2);
+
+        // An exact name match filtered out leaves the later stages to answer
+        db.add("/srv/api")?;
+        assert_eq!(db.query(&["api".to_string()])?[0].path, "/srv/api");
+        let results = db.query_with(&["api".to_string()], &options)?;
+        let paths: Vec<&str> = results.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["/srv/api-busy-fresh"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_by_project_type() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let crate_dir = temp_dir.path().join("app-crate");
+        let web_dir = temp_dir.path().join("app-web");
+        let plain_dir = temp_dir.path().join("app-notes");
+        for dir in [&crate_dir, &web_dir, &plain_dir] {
+            fs::create_dir(dir)?;
+        }
+        fs::write(crate_dir.join("Cargo.toml"), "[package]\n")?;
+        fs::create_dir(crate_dir.join(".git"))?;
+        fs::write(web_dir.join("package.json"), "{}")?;
+        assert_eq!(project::detect(&crate_dir).as_deref(), Some("rust,git"));
+        assert_eq!(project::detect(&plain_dir), None);
+
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for dir in [&crate_dir, &web_dir, &plain_dir] {
+            db.add(&dir.to_string_lossy())?;
+        }
+
+        let by_type = |kind| -> anyhow::Result<Vec<String>> {
+            let options = QueryOptions {
+                filter: QueryFilter {
+                    project_type: Some(kind),
+                    ..QueryFilter::default()
+                },
+                ..QueryOptions::default()
+            };
+            let results = db.query_with(&["app".to_string()], &options)?;
+            Ok(results.into_iter().map(|entry| entry.path).collect())
+        };
+        let crate_path = crate_dir.to_string_lossy().into_owned();
+        assert_eq!(
+            by_type(project::ProjectType::Rust)?,
+            vec![crate_path.clone()]
+        );
+        assert_eq!(by_type(project::ProjectType::Git)?, vec![crate_path]);
+        assert_eq!(by_type(project::ProjectType::Node)?.len(), 1);
+        assert!(by_type(project::ProjectType::Python)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_use_mtime_ranks_recently_modified_dirs_higher() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let stale = temp_dir.path().join("proj-a");
+        let fresh = temp_dir.path().join("proj-b");
+        fs::create_dir(&stale)?;
+        fs::create_dir(&fresh)?;
+        let month_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(30 * 86_400);
+        fs::File::open(&stale)?.set_modified(month_ago)?;
+
+        let best = |use_mtime: bool| -> anyhow::Result<String> {
+            let config = Config {
+                use_mtime,
+                ..Config::default()
+            };
+            let mut db = Database::open(&db_path, config)?;
+            for dir in [&stale, &fresh] {
+                db.add(&dir.to_string_lossy())?;
+            }
+            // Identical navigation frecency
+            rusqlite::Connection::open(&db_path)?.execute(
+                "UPDATE dirs SET last_access = ?1, visits_total = 1",
+                [chrono::Utc::now()],
+            )?;
+            Ok(db.query(&["proj".to_string()])?.remove(0).path)
+        };
+
+        assert_eq!(best(false)?, stale.to_string_lossy());
+        assert_eq!(best(true)?, fresh.to_string_lossy());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_to_base() {
+        let base = Path::new("/home/me/work");
+        assert_eq!(
+            nav::relative_to("/home/me/work/api/src", base).as_deref(),
+            Some("api/src")
+        );
+        assert_eq!(
+            nav::relative_to("/home/me/work", base).as_deref(),
+            Some(".")
+        );
+        // Outside the base, including a sibling sharing its name as a prefix
+        assert_eq!(nav::relative_to("/home/me/notes", base), None);
+        assert_eq!(nav::relative_to("/home/me/work2/api", base), None);
+    }
+
+    #[test]
+    fn test_auto_clean_runs_once_per_interval() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let lock = temp_dir.path().join("db.autoclean");
+        let now = chrono::Utc::now();
+
+        // While one process holds the claim, others skip
+        let claim = db::claim_auto_clean(&lock, now)?;
+        assert!(claim.is_some());
+        assert!(db::claim_auto_clean(&lock, now)?.is_none());
+        drop(claim);
+
+        // Released, but the last run is still recent
+        assert!(db::claim_auto_clean(&lock, now + chrono::Duration::minutes(5))?.is_none());
+        let later = now + chrono::Duration::minutes(db::AUTO_CLEAN_INTERVAL_MINUTES + 1);
+        assert!(db::claim_auto_clean(&lock, later)?.is_some());
+
+        // Two opens in a row: only the first one scans and purges
+        let db_dir = temp_dir.path().join("db");
+        let db_path = db_dir.join("db.sqlite");
+        let config = Config {
+            auto_clean_on_startup: true,
+            ..Config::default()
+        };
+        let insert_gone = |path: &str| -> anyhow::Result<()> {
+            let conn = rusqlite::Connection::open(&db_path)?;
+            conn.execute(
+                "INSERT INTO dirs (path, last_access, visits_total) VALUES (?1, ?2, 1)",
+                rusqlite::params![path, chrono::Utc::now()],
+            )?;
+            Ok(())
+        };
+        drop(Database::open(&db_path, Config::default())?);
+        insert_gone("/xneo-test-gone/a")?;
+        let db = Database::open(&db_path, config.clone())?;
+        assert_eq!(db.get_stats()?.total_entries, 0);
+        drop(db);
+
+        insert_gone("/xneo-test-gone/b")?;
+        let db = Database::open(&db_path, config)?;
+        assert_eq!(db.get_stats()?.total_entries, 1);
+        assert!(db_dir.join("db.autoclean").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_timeout_returns_best_so_far() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let db = Database::open(&db_path, Config::default())?;
+        let mut conn = rusqlite::Connection::open(&db_path)?;
+        let tx = conn.transaction()?;
+        let now = chrono::Utc::now();
+        for i in 0..20_000 {
+            tx.execute(
+                "INSERT INTO dirs (path, last_access, visits_total) VALUES (?1, ?2, ?3)",
+                rusqlite::params![format!("/srv/workspace/project-{i}"), now, i + 1],
+            )?;
+        }
+        tx.commit()?;
+
+        let options = QueryOptions {
+            timeout: Some(std::time::Duration::ZERO),
+            ..QueryOptions::default()
+        };
+        let started = std::time::Instant::now();
+        let results = db.query_with(&["wrkprj".to_string()], &options)?;
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        assert!(!results.is_empty());
+
+        // Only the best-ranked candidates were scanned
+        let trace = db.explain(&["wrkprj".to_string()], &options)?;
+        assert!(trace.timed_out);
+        assert!(trace.stages.iter().all(|stage| stage.candidates < 20_000));
+        assert_eq!(trace.top[0].path, "/srv/workspace/project-19999");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark_positions_order_the_list() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for name in ["alpha", "beta", "gamma", "delta"] {
+            db.add_bookmark(name, &format!("/srv/{name}"))?;
+        }
+        db.add_bookmark_alias("gamma", "g")?;
+        let names = |db: &Database| -> anyhow::Result<Vec<String>> {
+            Ok(db.get_bookmarks()?.into_iter().map(|b| b.name).collect())
+        };
+        assert_eq!(names(&db)?, ["alpha", "beta", "delta", "gamma"]);
+
+        // Placed bookmarks first, through aliases too; the rest by name
+        assert!(db.set_bookmark_position("g", Some(1))?);
+        assert!(db.set_bookmark_position("delta", Some(2))?);
+        assert!(!db.set_bookmark_position("missing", Some(3))?);
+        assert_eq!(names(&db)?, ["gamma", "delta", "alpha", "beta"]);
+        assert_eq!(db.get_bookmarks()?[0].position, Some(1));
+
+        // Re-pointing a bookmark keeps its place, usage and aliases
+        db.add_bookmark_alias("delta", "d")?;
+        db.resolve_bookmark("d")?;
+        db.add_bookmark("delta", "/srv/delta2")?;
+        let delta = db.get_bookmarks()?.remove(1);
+        assert_eq!(delta.path, "/srv/delta2");
+        assert_eq!((delta.position, delta.use_count), (Some(2), 1));
+        assert_eq!(db.get_bookmark("d")?.as_deref(), Some("/srv/delta2"));
+
+        assert!(db.set_bookmark_position("gamma", None)?);
+        assert_eq!(names(&db)?, ["delta", "alpha", "beta", "gamma"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_root() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mono = temp_dir.path().join("mono");
+        let api = mono.join("packages/api");
+        let web = mono.join("packages/web");
+        let notes = temp_dir.path().join("notes");
+        for dir in [&api, &web, &notes] {
+            fs::create_dir_all(dir)?;
+        }
+        fs::create_dir(mono.join(".git"))?;
+        // A nested project still belongs to the outermost one
+        fs::write(api.join("Cargo.toml"), "[package]\n")?;
+
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for dir in [&api, &notes, &mono, &web] {
+            db.add(&dir.to_string_lossy())?;
+        }
+        let path = |dir: &Path| dir.to_string_lossy().into_owned();
+        let entries: Vec<DirEntry> = [&api, &notes, &mono, &web]
+            .into_iter()
+            .map(|dir| DirEntry {
+                path: path(dir),
+                last_access: chrono::Utc::now(),
+                visits: 1,
+                rank: 1.0,
+            })
+            .collect();
+
+        let groups = db.group_by_root(entries)?;
+        let summary: Vec<(String, Vec<String>)> = groups
+            .into_iter()
+            .map(|group| {
+                let members = group.members.into_iter().map(|e| e.path).collect();
+                (group.root, members)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (path(&mono), vec![path(&api), path(&mono), path(&web)]),
+                (path(&notes), vec![path(&notes)]),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_resolves_relative_paths() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("xneo-relative/./sub/../proj")?;
+
+        let cwd = std::env::current_dir()?;
+        let expected = nav::normalize_path(&cwd.join("xneo-relative/proj").to_string_lossy());
+        let paths: Vec<String> = db.top(10)?.into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, vec![expected]);
+
+        assert_eq!(
+            nav::absolute_path("../api", Path::new("/work/web")),
+            "/work/api"
+        );
+        assert_eq!(
+            nav::absolute_path("/srv//app/", Path::new("/work")),
+            "/srv/app"
+        );
+        assert_eq!(
+            nav::absolute_path("C:\\Users\\me", Path::new("/work")),
+            "C:/Users/me"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_widget_scripts_bind_ctrl_g() {
+        let expected = [
+            ("fish", r"bind \cg __xneo_widget"),
+            ("bash", r#"bind -x '"\C-g": __xneo_widget'"#),
+            ("zsh", "bindkey '^G' __xneo_widget"),
+        ];
+        for (name, binding) in expected {
+            let script = shell::widget_script(name).unwrap();
+            assert!(script.contains(binding), "{name} widget lacks {binding}");
+            assert!(script.contains("xneo query --top --pick"));
+        }
+        assert!(shell::widget_script("zsh")
+            .unwrap()
+            .contains("zle -N __xneo_widget"));
+        // Neither prompt hook runs for a widget, so they record jumps themselves
+        for name in ["bash", "zsh"] {
+            assert!(shell::widget_script(name)
+                .unwrap()
+                .contains("__xneo_add_hook\n"));
+        }
+        assert!(shell::widget_script("tcsh").is_none());
+    }
+
+    #[test]
+    fn test_query_skip_path() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        for _ in 0..3 {
+            db.add("/work/api/project")?;
+        }
+        db.add("/play/project")?;
+
+        let keywords = ["project".to_string()];
+        let best = db.query_with(&keywords, &QueryOptions::default())?;
+        assert_eq!(best[0].path, "/work/api/project");
+
+        let options = QueryOptions {
+            skip_path: Some("/work/api/project/".into()),
+            ..QueryOptions::default()
+        };
+        let results = db.query_with(&keywords, &options)?;
+        let paths: Vec<&str> = results.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/play/project"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_visits_per_day() -> anyhow::Result<()> {
+        use chrono::{Local, TimeZone};
+
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        let today = Local::now().date_naive();
+        let noon = |days_ago: u64| {
+            let day = today - chrono::Days::new(days_ago);
+            Local
+                .from_local_datetime(&day.and_hms_opt(12, 0, 0).unwrap())
+                .earliest()
+                .unwrap()
+                .with_timezone(&chrono::Utc)
+        };
+        // Three visits two days ago, none yesterday, one today, and one
+        // outside the window
+        for path in ["/a", "/b", "/a"] {
+            db.add_at(path, noon(2))?;
+        }
+        db.add_at("/c", noon(0))?;
+        db.add_at("/old", noon(9))?;
+
+        let days = db.visits_per_day(3)?;
+        let expected = vec![
+            (today - chrono::Days::new(2), 3),
+            (today - chrono::Days::new(1), 0),
+            (today, 1),
+        ];
+        assert_eq!(days, expected);
+        assert!(db.visits_per_day(0)?.is_empty());
+        assert!(db.visits_per_day(u32::MAX).is_err());
+
+        let heatmap = display::render_active_days(&days);
+        assert!(heatmap.contains("Total visits: 4 on 2 active days"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_results_serialize_as_json() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), Config::default())?;
+        db.add("/work/api ")?;
+        db.add("/work/api-old")?;
+
+        let results = db.query(&["api".to_string()])?;
+        let json = serde_json::to_value(&results)?;
+        let array = json.as_array().unwrap();
+        assert_eq!(array.len(), 2);
+        for (value, entry) in array.iter().zip(&results) {
+            // Trailing whitespace survives, unlike in line-based output
+            assert_eq!(value["path"], entry.path.as_str());
+            assert_eq!(value["visits"], 1);
+            assert_eq!(value["rank"].as_f64(), Some(entry.rank));
+            let at = value["last_access"].as_str().unwrap();
+            assert_eq!(chrono::DateTime::parse_from_rfc3339(at)?, entry.last_access);
+        }
+        assert!(results.iter().any(|entry| entry.path == "/work/api "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefer_git_root_ranks_repository_roots_higher() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("db.sqlite");
+        let root = temp_dir.path().join("shop");
+        let deep = root.join("apps/shop");
+        fs::create_dir_all(&deep)?;
+        fs::create_dir(root.join(".git"))?;
+
+        let query = |prefer_git_root: bool| -> anyhow::Result<Vec<DirEntry>> {
+            let config = Config {
+                prefer_git_root,
+                ..Config::default()
+            };
+            let mut db = Database::open(&db_path, config)?;
+            db.add(&root.to_string_lossy())?;
+            db.add(&deep.to_string_lossy())?;
+            // The subdirectory is the more frecent one
+            let conn = rusqlite::Connection::open(&db_path)?;
+            conn.execute("UPDATE dirs SET last_access = ?1", [chrono::Utc::now()])?;
+            conn.execute(
+                "UPDATE dirs SET visits_total = CASE WHEN path = ?1 THEN 2 ELSE 1 END",
+                [deep.to_string_lossy()],
+            )?;
+            db.query(&["shop".to_string()])
+        };
+
+        let plain = query(false)?;
+        let preferred = query(true)?;
+        assert_eq!(plain[0].path, deep.to_string_lossy());
+        assert_eq!(preferred[0].path, root.to_string_lossy());
+        // The boost only orders results, reported ranks stay frecency
+        assert!((plain[1].rank - preferred[0].rank).abs() < 1e-6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_insensitive_bookmarks_fold_on_store() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config {
+            bookmark_case_insensitive: true,
+            ..Config::default()
+        };
+        let mut db = Database::open(&temp_dir.path().join("db.sqlite"), config)?;
+
+        // Another spelling re-points the bookmark instead of adding one
+        db.add_bookmark("work", "/home/user/work")?;
+        db.add_bookmark("Work", "/srv/work")?;
+        let bookmarks = db.get_bookmarks()?;
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].name, "work");
+        assert_eq!(bookmarks[0].path, "/srv/work");
+
+        // An alias can't shadow a bookmark spelled differently
+        db.add_bookmark("w", "/srv/w")?;
+        assert!(db.add_bookmark_alias("work", "W").is_err());
+        assert_eq!(db.get_bookmark("W")?.as_deref(), Some("/srv/w"));
+
+        // A bookmark takes over an alias spelled differently
+        db.add_bookmark_alias("work", "job")?;
+        db.add_bookmark("JOB", "/srv/job")?;
+        assert_eq!(db.get_bookmark("job")?.as_deref(), Some("/srv/job"));
+        let bookmarks = db.get_bookmarks()?;
+        let work = bookmarks.iter().find(|b| b.name == "work").unwrap();
+        assert!(work.aliases.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_file_compares_on_disk() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let project = temp_dir.path().join("Project");
+        let other = temp_dir.path().join("other");
+        fs::create_dir(&project)?;
+        fs::create_dir(&other)?;
+
+        assert_eq!(nav::same_file(&project, &project.join(".")), Some(true));
+        assert_eq!(nav::same_file(&project, &other), Some(false));
+        // Undecided without both on disk; nothing is written to find out
+        let missing = temp_dir.path().join("missing");
+        assert_eq!(nav::same_file(&project, &missing), None);
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2);
+
+        Ok(())
+    }
+}
@@ -1,28 +1,55 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use colored::*;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::collections::HashMap;
 use std::env;
-use std::io::{self, Write};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 mod config;
 mod db;
+mod display;
+mod import;
+mod insights;
+mod nav;
+mod picker;
+mod project;
 mod shell;
+mod stream;
+mod theme;
 
-use config::Config;
-use db::Database;
+use config::{Config, ConfigReset};
+use db::{
+    Bookmark, CleanSummary, Database, DirEntry, QueryFilter, QueryOptions, Shortcut,
+    DEFAULT_QUERY_LIMIT,
+};
 
 #[derive(Parser, Debug)]
-#[command(name = "xneo", version = "0.2.0", author = "Your Name")]
+#[command(name = "xneo", version, author = "Your Name")]
 #[command(about = "A smarter cd command with memory and intelligence")]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+// Parsed once per run, so the size of `Query`'s many flags doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// [Internal] Adds a directory to the database
-    Add { path: String },
+    Add {
+        path: String,
+
+        /// Print the decision to stderr without writing to the database
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Record the visit at this RFC 3339 time instead of now, e.g. when
+        /// replaying an imported shell history
+        #[arg(long, value_name = "RFC3339", value_parser = parse_rfc3339)]
+        at: Option<DateTime<Utc>>,
+    },
 
     /// [Internal] Queries the database for directories
     Query {
@@ -32,15 +59,153 @@ enum Commands {
         #[arg(long)]
         suggest: bool,
 
+        /// With --suggest, print `name<TAB>fullpath` pairs for shell completion
+        #[arg(long, requires = "suggest")]
+        describe: bool,
+
         /// [Internal] Find a matching ancestor directory
         #[arg(long)]
         ancestor: bool,
+
+        /// With --ancestor, look at most N levels above the current directory
+        #[arg(long, value_name = "N", requires = "ancestor")]
+        depth: Option<usize>,
+
+        /// With --ancestor, return the topmost match instead of the nearest
+        #[arg(long, requires = "ancestor")]
+        farthest: bool,
+
+        /// [Internal] Find the most frecent tracked directory below the current one with this name
+        #[arg(long, conflicts_with = "ancestor")]
+        descendant: bool,
+
+        /// Prefer matches inside the current directory tree
+        #[arg(long)]
+        prefer_cwd: bool,
+
+        /// Directory to treat as the current one (defaults to the process cwd)
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+
+        /// Override the recency weight for this query (0.0 = frequency only, 1.0 = recency only)
+        #[arg(long, value_name = "WEIGHT", value_parser = parse_age_weight)]
+        age_weight: Option<f64>,
+
+        /// Skip the first N ranked results
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Return at most N results
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Match directories containing any of the keywords, most keywords first
+        #[arg(long)]
+        any: bool,
+
+        /// List the highest-ranked directories instead of matching keywords
+        #[arg(long)]
+        top: bool,
+
+        /// List directories not visited for this long (e.g. 30d, 2w, 12h), most visited first
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "top")]
+        not_recent: Option<chrono::Duration>,
+
+        /// Leave the current directory (see --cwd) out of the results, so
+        /// `x name` from inside the best match goes to the next one
+        #[arg(long)]
+        skip_cwd: bool,
+
+        /// Only directories visited at least N times
+        #[arg(long, value_name = "N")]
+        min_visits: Option<u32>,
+
+        /// Only directories visited within this long (e.g. 30d, 2w, 12h)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "not_recent")]
+        max_age: Option<chrono::Duration>,
+
+        /// Only directories holding this kind of project, detected from marker
+        /// files (Cargo.toml, package.json, pyproject.toml, .git, ...) when visited
+        #[arg(long = "type", value_name = "TYPE", value_enum)]
+        project_type: Option<project::ProjectType>,
+
+        /// Output template with {path}, {visits}, {rank} and {last_access} placeholders
+        #[arg(long, value_name = "TEMPLATE", default_value = display::DEFAULT_ENTRY_FORMAT)]
+        format: String,
+
+        /// Print the matches as a JSON array of {path, last_access, visits,
+        /// rank} objects, without the bookmark and `..` shortcuts
+        #[arg(long, conflicts_with_all = ["suggest", "pick", "stdin_query", "keywords_from_file", "include_bookmarks", "ancestor", "descendant", "top", "not_recent"])]
+        json: bool,
+
+        /// Choose one of several matches with fzf and print only that one
+        #[arg(long, conflicts_with = "suggest")]
+        pick: bool,
+
+        /// Read one query per line from stdin and answer each with a block of
+        /// results ended by an empty line, keeping the database open
+        #[arg(long, conflicts_with_all = ["suggest", "ancestor", "top", "not_recent", "pick"])]
+        stdin_query: bool,
+
+        /// Resolve each line of this file as a query, printing `query<TAB>best result`
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["suggest", "ancestor", "top", "not_recent", "pick", "stdin_query"])]
+        keywords_from_file: Option<PathBuf>,
+
+        /// Also list bookmarks, marking bookmarked paths with `<TAB>[name]`
+        #[arg(long, conflicts_with = "suggest")]
+        include_bookmarks: bool,
+
+        /// Print how the query was resolved (stage, candidates, top scores) to stderr as JSON
+        #[arg(long, conflicts_with_all = ["ancestor", "descendant", "top", "not_recent", "stdin_query"])]
+        explain: bool,
+
+        /// Stop matching after N milliseconds and print the best results so far
+        #[arg(long, value_name = "N")]
+        timeout_ms: Option<u64>,
+
+        /// With --pick, first choose a project root (the outermost tracked
+        /// project holding results), then one of the results under it
+        #[arg(long, requires = "pick", conflicts_with_all = ["include_bookmarks", "paths_relative_to"])]
+        group_by_root: bool,
+
+        /// With --pick, show an N-line preview of the highlighted directory
+        #[arg(long, value_name = "N", requires = "pick")]
+        preview_lines: Option<usize>,
+
+        /// Print results under this directory relative to it; others stay absolute
+        #[arg(long, value_name = "BASE")]
+        paths_relative_to: Option<PathBuf>,
+
+        /// With --paths-relative-to, leave out results outside the base
+        #[arg(long, requires = "paths_relative_to")]
+        only_under: bool,
     },
 
     /// Generates shell initialization script
     Init {
         /// Shell type: fish, bash, zsh, powershell
         shell: String,
+
+        /// Print the rc file to edit and the line to add to it instead of the script
+        #[arg(long)]
+        print_path: bool,
+
+        /// (fish) Log errors from the hook's background `xneo add` to
+        /// ~/.cache/xneo/add.log so `xneo doctor` can show them
+        #[arg(long)]
+        report_errors: bool,
+    },
+
+    /// Prints shell key bindings; `--widget` binds Ctrl-G to an fzf picker
+    /// over the top directories (source the output after `xneo init`)
+    Completions {
+        /// Shell type: fish, bash, zsh
+        shell: String,
+
+        /// Print the Ctrl-G widget: jump to the picked directory, or insert
+        /// it at the cursor when the command line isn't empty
+        #[arg(long)]
+        widget: bool,
     },
 
     /// Manages bookmarks
@@ -50,13 +215,116 @@ enum Commands {
     },
 
     /// Shows usage statistics
-    Stats,
+    Stats {
+        /// Redraw the statistics periodically until Ctrl-C
+        #[arg(long)]
+        watch: bool,
+
+        /// Refresh interval in seconds for --watch
+        #[arg(long, default_value_t = 2, requires = "watch")]
+        interval: u64,
+
+        /// Print every tracked directory as CSV (path,visits,last_access,rank), best first
+        #[arg(long, conflicts_with = "watch")]
+        csv: bool,
+
+        /// With --csv, only the N highest-ranked directories
+        #[arg(long, value_name = "N", requires = "csv")]
+        limit: Option<usize>,
+
+        /// Number of most and recently visited directories to list
+        #[arg(long, value_name = "N", default_value_t = db::DEFAULT_STATS_TOP, conflicts_with = "csv")]
+        top: usize,
+
+        /// Show a heatmap of visits per day over the last N days (up to
+        /// about ten years) instead
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=3660), conflicts_with_all = ["watch", "csv"])]
+        active_days: Option<u32>,
+    },
+
+    /// Summarizes navigation patterns from the local history
+    Insights,
+
+    /// Shows recent errors logged by the shell hook (see `init --report-errors`)
+    Doctor,
+
+    /// Lists recorded jumps, oldest first
+    History {
+        /// Only jumps at or after this time: a date (2024-05-14, local midnight),
+        /// an RFC 3339 timestamp, or a duration ago (12h, 3d, 2w)
+        #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only jumps before this time (exclusive), in the same formats as --since
+        #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+        until: Option<DateTime<Utc>>,
+    },
+
+    /// Recomputes the persisted rank of every directory
+    Rerank,
+
+    /// Compares the frecency rank of two directories, part by part
+    RankTest { a: String, b: String },
+
+    /// Prints version information
+    Version {
+        /// Also print file locations and SQLite/schema versions (for bug reports)
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Restores the entries removed by the last clean
+    Undo,
+
+    /// Forgets every tracked directory whose path starts with a prefix (undo with `xneo undo`)
+    ForgetPrefix {
+        prefix: String,
+
+        /// List what would be forgotten without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Updates directories and bookmarks after moving a tree (e.g. ~/work to ~/projects)
+    Move {
+        old_prefix: String,
+        new_prefix: String,
+    },
+
+    /// Imports directories from another jumper's data file (supported: autojump)
+    Import {
+        from: String,
+
+        /// Data file to read instead of the tool's default location
+        path: Option<PathBuf>,
+    },
 
     /// Database maintenance
     Clean {
         /// Skip confirmation prompt
         #[arg(short, long)]
         yes: bool,
+
+        /// Print machine-readable counts and exit with 0 (clean), 10 (removed) or 11 (stale left)
+        #[arg(long)]
+        porcelain: bool,
+
+        /// No progress spinners or colors (implied when output isn't a terminal)
+        #[arg(short, long, visible_alias = "no-progress")]
+        quiet: bool,
+
+        /// Also remove directories that still exist but are empty
+        #[arg(long)]
+        prune_empty: bool,
+
+        /// Only report the database file size, row counts and what a vacuum
+        /// would reclaim; nothing is removed
+        #[arg(long, conflicts_with_all = ["yes", "porcelain", "prune_empty"])]
+        report: bool,
     },
 
     /// Configuration management
@@ -69,13 +337,56 @@ enum Commands {
 #[derive(Subcommand, Debug)]
 enum BookmarkAction {
     /// Add a bookmark for current or specified directory
-    Add { name: String, path: Option<String> },
+    Add {
+        name: String,
+        path: Option<String>,
+
+        /// Store the path verbatim (e.g. `$HOME/work`) and expand it when read
+        #[arg(long)]
+        literal: bool,
+
+        /// Store the path even if it doesn't exist (e.g. an unmounted drive)
+        #[arg(long, visible_alias = "force")]
+        allow_missing: bool,
+
+        /// Place in `bookmark list` (1 first); see `bookmark reorder`
+        #[arg(long, value_name = "N")]
+        order: Option<u32>,
+    },
+    /// Set where a bookmark appears in `bookmark list` (1 first)
+    Reorder {
+        name: String,
+
+        /// New place; bookmarks without one follow, by name
+        #[arg(required_unless_present = "clear")]
+        position: Option<u32>,
+
+        /// Drop the bookmark's place so it sorts by name again
+        #[arg(long, conflicts_with = "position")]
+        clear: bool,
+    },
+    /// Add another name for an existing bookmark
+    Alias { existing: String, name: String },
     /// Remove a bookmark
     Remove { name: String },
     /// List all bookmarks
     List,
+    /// List bookmarks by how often they're jumped to
+    Stats,
+    /// Report malformed bookmarks, missing targets and duplicate targets
+    Check {
+        /// Remove bookmarks with an empty name or path
+        #[arg(long)]
+        fix: bool,
+    },
     /// Get bookmark path (internal use)
-    Get { name: String },
+    Get {
+        name: String,
+
+        /// Expand `~` and environment variables in the stored path
+        #[arg(long)]
+        expand: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -84,10 +395,18 @@ enum ConfigAction {
     Show,
     /// Edit configuration file
     Edit,
-    /// Reset to default configuration
-    Reset,
+    /// Reset to default configuration (the old file is kept as config.json.bak)
+    Reset {
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
     /// [Internal] Get a specific config value
     Get { key: String },
+    /// Print the config file and database locations
+    Path,
+    /// Show only the settings changed from the defaults (default -> current)
+    Diff,
 }
 
 fn main() -> Result<()> {
@@ -95,36 +414,344 @@ fn main() -> Result<()> {
         env::set_var("RUST_BACKTRACE", "0");
     }
 
+    // `RUST_LOG=debug xneo query foo` shows how the query was resolved
+    env_logger::init();
+
     let cli = Cli::parse();
     let config = Config::load()?;
+    theme::set(config.theme);
+
+    // Buffered adds never open SQLite, so the shell hook can't wait on a lock
+    if let Some(Commands::Add {
+        path,
+        dry_run: false,
+        at,
+    }) = &cli.command
+    {
+        if config.buffer_adds {
+            if config.skip_env_var().is_none() {
+                let at = at.unwrap_or_else(Utc::now);
+                Database::buffer_visit(&Database::db_path()?, path, at)?;
+            }
+            return Ok(());
+        }
+    }
+
     let mut db = Database::new(config.clone())?;
 
+    // The shell hook runs `add` on every directory change; stay quiet there
+    if let Some(warning) = db.schema_warning() {
+        if !matches!(cli.command, Some(Commands::Add { .. })) {
+            eprintln!("{}: {}", theme::warning("Warning"), warning);
+        }
+    }
+
     match cli.command {
-        Some(Commands::Init { shell }) => handle_init(&shell)?,
-        Some(Commands::Add { path }) => db.add(&path)?,
+        Some(Commands::Init {
+            shell,
+            print_path: true,
+            ..
+        }) => match shell::rc_snippet(&shell) {
+            Some((rc_file, snippet)) => println!("{}\n{}", nav::expand_path(rc_file), snippet),
+            None => {
+                eprintln!("{}: Unsupported shell: {}", theme::error("Error"), shell);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Init {
+            shell,
+            report_errors,
+            ..
+        }) => handle_init(&shell, &config, report_errors)?,
+        Some(Commands::Completions { shell, widget }) => {
+            if !widget {
+                anyhow::bail!(
+                    "Only --widget is available; completion for `x` comes with `xneo init {}`",
+                    shell
+                );
+            }
+            match shell::widget_script(&shell) {
+                Some(script) => print!("{}", script),
+                None => {
+                    eprintln!("{}: Unsupported shell: {}", theme::error("Error"), shell);
+                    eprintln!("Supported shells: fish, bash, zsh");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Add { path, dry_run, at }) => {
+            if dry_run {
+                let path = db::resolve_add_path(&path)?;
+                eprintln!("{}: {}", path, db.add_decision(&path));
+            } else if let Some(at) = at {
+                db.add_at(&path, at)?;
+            } else {
+                db.add(&path)?;
+            }
+        }
 
         // Update Query matching
         Some(Commands::Query {
             keywords,
             suggest,
+            describe,
             ancestor,
+            depth,
+            farthest,
+            descendant,
+            prefer_cwd,
+            cwd,
+            age_weight,
+            offset,
+            limit,
+            any,
+            top,
+            not_recent,
+            skip_cwd,
+            min_visits,
+            max_age,
+            project_type,
+            format,
+            json,
+            pick,
+            include_bookmarks,
+            stdin_query,
+            keywords_from_file,
+            explain,
+            timeout_ms,
+            group_by_root,
+            preview_lines,
+            paths_relative_to,
+            only_under,
         }) => {
-            if ancestor {
+            let cwd = match cwd {
+                Some(cwd) => cwd,
+                None => env::current_dir()?,
+            };
+
+            let fzf_args = pick.then(|| picker::fzf_args(&config, preview_lines));
+            let relative = paths_relative_to.map(|base| Relative { base, only_under });
+            let filter = QueryFilter {
+                min_visits,
+                max_age,
+                project_type,
+            };
+            if top || not_recent.is_some() {
+                let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+                // Filtering may drop entries, so fetch them all and cut after
+                let fetch = if filter.is_empty() {
+                    offset.saturating_add(limit)
+                } else {
+                    usize::MAX
+                };
+                let mut entries = match not_recent {
+                    Some(age) => {
+                        let cutoff = chrono::Utc::now()
+                            .checked_sub_signed(age)
+                            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+                        db.neglected(cutoff, fetch)?
+                    }
+                    None => db.top(fetch)?,
+                };
+                db.retain_filtered(&mut entries, &filter)?;
+                if skip_cwd {
+                    let cwd = nav::normalize_path(&cwd.to_string_lossy());
+                    entries.retain(|entry| entry.path != cwd);
+                }
+                entries.drain(..offset.min(entries.len()));
+                entries.truncate(limit);
+                match fzf_args.as_deref() {
+                    Some(args) if group_by_root => pick_by_root(&db, entries, &format, args)?,
+                    _ => {
+                        let entries = relativize(entries, relative.as_ref());
+                        let lines = result_lines(
+                            &db,
+                            &entries,
+                            &format,
+                            include_bookmarks,
+                            relative.as_ref(),
+                        )?;
+                        print_choices(lines, fzf_args.as_deref())?;
+                    }
+                }
+            } else if ancestor {
                 // If it's an ancestor query, call the new dedicated function
-                handle_ancestor_query(&keywords)?;
+                handle_ancestor_query(&keywords, &cwd, depth, farthest);
+            } else if descendant {
+                // Like --ancestor: a single name, and no output without a match
+                if let [name] = keywords.as_slice() {
+                    if let Some(entry) = db.find_descendant(name, &cwd)? {
+                        println!("{}", display::format_entry(&format, &entry));
+                    }
+                }
             } else {
                 // Otherwise, use the original query logic
-                handle_query(&db, &keywords, suggest)?;
+                let options = QueryOptions {
+                    prefer_cwd: prefer_cwd.then(|| cwd.clone()),
+                    age_weight,
+                    offset,
+                    limit,
+                    any,
+                    // Completions and live lists want every candidate
+                    auto_select_if_dominant: if suggest
+                        || stdin_query
+                        || keywords_from_file.is_some()
+                    {
+                        None
+                    } else {
+                        config.auto_select_if_dominant
+                    },
+                    filter,
+                    timeout: timeout_ms.map(std::time::Duration::from_millis),
+                    skip_path: skip_cwd.then(|| cwd.clone()),
+                };
+                if stdin_query {
+                    let (stdin, stdout) = (io::stdin().lock(), io::stdout().lock());
+                    stream::serve_queries(&db, stdin, stdout, &options, &format)?;
+                    return Ok(());
+                }
+                if let Some(file) = keywords_from_file {
+                    let input = io::BufReader::new(
+                        fs::File::open(&file)
+                            .with_context(|| format!("Failed to open {}", file.display()))?,
+                    );
+                    stream::resolve_queries(&db, input, io::stdout().lock(), &options, &format)?;
+                    return Ok(());
+                }
+
+                if explain {
+                    let trace = db.explain(&keywords, &options)?;
+                    eprintln!("{}", serde_json::to_string(&trace)?);
+                }
+
+                let output = QueryOutput {
+                    suggest,
+                    describe,
+                    format: &format,
+                    json,
+                    include_bookmarks,
+                    pick: fzf_args,
+                    group_by_root,
+                    relative,
+                };
+                handle_query(&db, &keywords, &output, &cwd, &options)?;
             }
         }
 
         Some(Commands::Bookmark { action }) => handle_bookmark(&mut db, action)?,
-        Some(Commands::Stats) => handle_stats(&db)?,
-        Some(Commands::Clean { yes }) => handle_clean(&mut db, yes)?,
+        Some(Commands::Stats {
+            csv: true, limit, ..
+        }) => print!(
+            "{}",
+            display::render_csv(&db.top(limit.unwrap_or(usize::MAX))?)
+        ),
+        Some(Commands::Stats {
+            active_days: Some(days),
+            ..
+        }) => print!("{}", display::render_active_days(&db.visits_per_day(days)?)),
+        Some(Commands::Stats {
+            watch,
+            interval,
+            top,
+            ..
+        }) => handle_stats(&db, watch, interval, top)?,
+        Some(Commands::Insights) => handle_insights(&db, &config)?,
+        Some(Commands::Doctor) => handle_doctor()?,
+        Some(Commands::History { since, until }) => {
+            for event in db.history_events(since, until)? {
+                let at = event.timestamp.with_timezone(&chrono::Local);
+                println!(
+                    "{}  {}",
+                    theme::muted(at.format("%Y-%m-%d %H:%M").to_string()),
+                    theme::path(&event.path)
+                );
+            }
+        }
+        Some(Commands::Rerank) => {
+            let count = db.refresh_ranks()?;
+            println!(
+                "{}: Refreshed ranks for {} directories",
+                theme::success("✓"),
+                theme::value(count.to_string())
+            );
+        }
+        Some(Commands::RankTest { a, b }) => {
+            let now = Utc::now();
+            let a = db.rank_breakdown(&shellexpand::tilde(&a), &now)?;
+            let b = db.rank_breakdown(&shellexpand::tilde(&b), &now)?;
+            print!("{}", display::render_rank_test(&a, &b));
+        }
+        Some(Commands::Version { verbose }) => print!(
+            "{}",
+            display::render_version(verbose, &Config::config_path()?, &Database::db_path()?)
+        ),
+        Some(Commands::Undo) => {
+            let restored = db.undo()?;
+            if restored == 0 {
+                println!("{}: Nothing to undo.", theme::info("ℹ"));
+            } else {
+                println!(
+                    "{}: Restored {} entries.",
+                    theme::success("✓"),
+                    theme::value(restored.to_string())
+                );
+            }
+        }
+        Some(Commands::Import { from, path }) => handle_import(&mut db, &from, path)?,
+        Some(Commands::ForgetPrefix {
+            prefix,
+            dry_run,
+            yes,
+        }) => handle_forget_prefix(&mut db, &shellexpand::tilde(&prefix), dry_run, yes)?,
+        Some(Commands::Move {
+            old_prefix,
+            new_prefix,
+        }) => {
+            let summary = db.move_prefix(
+                &shellexpand::tilde(&old_prefix),
+                &shellexpand::tilde(&new_prefix),
+            )?;
+            println!(
+                "{}: Rewrote {} directories and {} bookmarks.",
+                theme::success("✓"),
+                theme::value(summary.dirs.to_string()),
+                theme::value(summary.bookmarks.to_string())
+            );
+        }
+        Some(Commands::Clean { report: true, .. }) => {
+            print!("{}", display::render_storage_report(&db.storage_report()?));
+        }
+        Some(Commands::Clean {
+            yes,
+            porcelain,
+            quiet,
+            prune_empty,
+            ..
+        }) => {
+            if porcelain {
+                handle_clean_porcelain(&mut db, yes, prune_empty)?;
+            } else {
+                let interactive = display::is_interactive(quiet);
+                handle_clean(&mut db, yes, prune_empty, interactive)?;
+            }
+        }
         Some(Commands::Config { action }) => handle_config(&config, action)?,
         None => {
-            if let Some(home) = dirs::home_dir() {
-                print!("{}", home.display());
+            if config
+                .bare_invocation
+                .prints_home(io::stdout().is_terminal())
+            {
+                if let Some(dir) = config.landing_dir() {
+                    print!("{}", dir.display());
+                }
+            } else {
+                Cli::command().print_help()?;
+                let stats = db.get_stats()?;
+                println!(
+                    "\nTracking {} directories ({} visits). Jump with `x <keywords>`.",
+                    theme::value(stats.total_entries.to_string()),
+                    theme::value(stats.total_visits.to_string())
+                );
             }
         }
     }
@@ -132,82 +759,332 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Parses `<n><unit>` with unit `h`, `d` or `w`; a bare number means days
+fn parse_duration(value: &str) -> std::result::Result<chrono::Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "d"),
+    };
+    let amount: i64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a duration like 30d, 2w or 12h", value))?;
+
+    let duration = match unit {
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => return Err(format!("unknown duration unit '{}' (use h, d or w)", unit)),
+    };
+    // Durations are subtracted from now, so they must reach a valid time
+    duration
+        .filter(|duration| Utc::now().checked_sub_signed(*duration).is_some())
+        .ok_or_else(|| format!("'{}' is too long a duration", value))
+}
+
+/// Parses `add --at`, which only takes an exact RFC 3339 timestamp
+fn parse_rfc3339(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| {
+            format!(
+                "'{}' is not an RFC 3339 timestamp like 2024-05-14T09:30:00Z",
+                value
+            )
+        })
+}
+
+/// Parses a `--since`/`--until` bound: a local date, an RFC 3339 timestamp
+/// or a duration before now (see `parse_duration`)
+fn parse_time_bound(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date
+            .and_time(chrono::NaiveTime::MIN)
+            .and_local_timezone(chrono::Local)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+            .ok_or_else(|| format!("'{}' has no local midnight", value));
+    }
+    parse_duration(value)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a date (2024-05-14), timestamp or duration (3d)",
+                value
+            )
+        })
+}
+
+fn parse_age_weight(value: &str) -> std::result::Result<f64, String> {
+    let weight: f64 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))?;
+    if (0.0..=1.0).contains(&weight) {
+        Ok(weight)
+    } else {
+        Err("age weight must be between 0.0 and 1.0".to_string())
+    }
+}
+
 // New: function to handle parent directory queries
-fn handle_ancestor_query(keywords: &[String]) -> Result<()> {
+fn handle_ancestor_query(keywords: &[String], cwd: &Path, depth: Option<usize>, farthest: bool) {
     // Parent directory query only accepts a single word
     if keywords.len() != 1 {
-        return Ok(());
+        return;
     }
-    let name_to_find = &keywords[0];
 
-    let current_dir = env::current_dir()?;
-    for ancestor in current_dir.ancestors() {
-        if let Some(dir_name) = ancestor.file_name().and_then(|s| s.to_str()) {
-            if dir_name == name_to_find {
-                // Found! Print the path and exit successfully
-                print!("{}", ancestor.display());
-                return Ok(());
-            }
+    // Without a match, print nothing and exit quietly
+    // The shell script will decide what to do next based on whether there is output
+    if let Some(ancestor) = nav::find_ancestor(&keywords[0], cwd, depth, farthest) {
+        print!("{}", ancestor.display());
+    }
+}
+
+fn handle_init(shell: &str, config: &Config, report_errors: bool) -> Result<()> {
+    let Some(script) = shell::init_script(shell) else {
+        eprintln!("{}: Unsupported shell: {}", theme::error("Error"), shell);
+        eprintln!("Supported shells: fish, bash, zsh, powershell");
+        std::process::exit(1);
+    };
+    let post_jump = config.post_jump_command.as_deref();
+    let mut script = shell::with_post_jump(script, post_jump);
+
+    if report_errors {
+        if shell != "fish" {
+            anyhow::bail!("--report-errors is only supported for fish");
+        }
+        let log = shell::add_log_path().context("Failed to find user's cache directory")?;
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create log directory at {:?}", dir))?;
         }
+        script = shell::with_add_log(&script, &log);
     }
 
-    // If the loop finishes without finding anything, print nothing and exit quietly
-    // The shell script will decide what to do next based on whether there is output
+    print!("{}", script);
     Ok(())
 }
 
-fn handle_init(shell: &str) -> Result<()> {
-    match shell {
-        "fish" => print!("{}", shell::FISH_INIT_SCRIPT),
-        "bash" => print!("{}", shell::BASH_INIT_SCRIPT),
-        "zsh" => print!("{}", shell::ZSH_INIT_SCRIPT),
+fn handle_forget_prefix(db: &mut Database, prefix: &str, dry_run: bool, yes: bool) -> Result<()> {
+    let paths = db.paths_with_prefix(prefix)?;
+    if paths.is_empty() {
+        println!(
+            "{}: No tracked directories start with {}.",
+            theme::info("ℹ"),
+            prefix
+        );
+        return Ok(());
+    }
+
+    for path in &paths {
+        println!("  - {}", theme::alert(path));
+    }
+    if dry_run {
+        println!(
+            "\n{}: Would forget {} directories.",
+            theme::info("ℹ"),
+            theme::value(paths.len().to_string())
+        );
+        return Ok(());
+    }
+
+    if yes || confirm(&format!("Forget these {} directories?", paths.len()))? {
+        let removed = db.purge(&paths)?;
+        println!(
+            "{}: Forgot {} directories. Run `xneo undo` to bring them back.",
+            theme::success("✓"),
+            theme::value(removed.to_string())
+        );
+    } else {
+        println!("\n{}: No changes were made.", theme::info("ℹ"));
+    }
+    Ok(())
+}
+
+fn handle_import(db: &mut Database, from: &str, path: Option<PathBuf>) -> Result<()> {
+    let (path, visits) = match from {
+        "autojump" => {
+            let Some(path) = path.or_else(import::autojump_data_path) else {
+                anyhow::bail!("Could not locate autojump's data directory; pass the file path");
+            };
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            (path, import::parse_autojump(&contents))
+        }
         _ => {
-            eprintln!("{}: Unsupported shell: {}", "Error".red().bold(), shell);
-            eprintln!("Supported shells: fish, bash, zsh, powershell");
+            eprintln!(
+                "{}: Unsupported import source: {}",
+                theme::error("Error"),
+                from
+            );
+            eprintln!("Supported sources: autojump");
             std::process::exit(1);
         }
-    }
+    };
+
+    let imported = db.import_visits(&visits)?;
+    println!(
+        "{}: Imported {} of {} directories from {}.",
+        theme::success("✓"),
+        theme::value(imported.to_string()),
+        theme::value(visits.len().to_string()),
+        theme::path(path.display().to_string())
+    );
     Ok(())
 }
 
-fn handle_query(db: &Database, keywords: &[String], suggest: bool) -> Result<()> {
+/// How `handle_query` prints what it finds
+struct QueryOutput<'a> {
+    suggest: bool,
+    describe: bool,
+    format: &'a str,
+    /// Print the results as JSON instead of formatted lines
+    json: bool,
+    include_bookmarks: bool,
+    /// fzf arguments when several matches should be narrowed to one
+    pick: Option<Vec<String>>,
+    /// Pick a project root first, then a result under it
+    group_by_root: bool,
+    relative: Option<Relative>,
+}
+
+/// `--paths-relative-to` and `--only-under`
+struct Relative {
+    base: PathBuf,
+    only_under: bool,
+}
+
+impl Relative {
+    /// An absolute `path` as printed: relative to the base when under it,
+    /// unchanged outside it, or `None` when `only_under` leaves it out
+    fn apply(&self, path: &str) -> Option<String> {
+        let base = PathBuf::from(nav::normalize_path(&nav::expand_path(
+            &self.base.to_string_lossy(),
+        )));
+        match nav::relative_to(path, &base) {
+            Some(relative) => Some(relative),
+            None if self.only_under => None,
+            None => Some(path.to_string()),
+        }
+    }
+}
+
+/// `entries` with paths under `relative.base` rewritten relative to it, and
+/// those outside dropped with `only_under`
+fn relativize(entries: Vec<DirEntry>, relative: Option<&Relative>) -> Vec<DirEntry> {
+    let Some(relative) = relative else {
+        return entries;
+    };
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let path = relative.apply(&entry.path)?;
+            Some(DirEntry { path, ..entry })
+        })
+        .collect()
+}
+
+/// A bookmark target (expanded) as printed, see `Relative::apply`
+fn bookmark_output(path: &str, relative: Option<&Relative>) -> Option<String> {
+    let path = nav::expand_path(path);
+    match relative {
+        Some(relative) => relative.apply(&nav::normalize_path(&path)),
+        None => Some(path),
+    }
+}
+
+fn handle_query(
+    db: &Database,
+    keywords: &[String],
+    output: &QueryOutput,
+    cwd: &Path,
+    options: &QueryOptions,
+) -> Result<()> {
+    let keywords = &db::trim_keywords(keywords);
+    if output.json {
+        let results = if keywords.is_empty() {
+            Vec::new()
+        } else {
+            relativize(db.query_with(keywords, options)?, output.relative.as_ref())
+        };
+        println!("{}", serde_json::to_string(&results)?);
+        return Ok(());
+    }
     if keywords.is_empty() {
         return Ok(());
     }
 
-    // Prioritize checking bookmarks
+    // Relative jumps (`..`, `...`) and bookmarks win over directory matches
     let keyword = keywords.join(" ");
-    if keywords.len() == 1 {
-        if let Some(path) = db.get_bookmark(&keyword)? {
-            println!("{}", path);
+    match db.shortcut(keywords)? {
+        Some(Shortcut::Dots) => {
+            if let Some(path) = nav::resolve_dots(&keyword, cwd) {
+                println!("{}", path.display());
+            }
+            return Ok(());
+        }
+        Some(Shortcut::Bookmark { name, path }) => {
+            db.record_bookmark_use(&name)?;
+            if let Some(path) = bookmark_output(&path, output.relative.as_ref()) {
+                println!("{}", path);
+            }
             return Ok(());
         }
+        // Partial bookmark names: offer every bookmark with that prefix
+        Some(Shortcut::BookmarkPrefix(candidates)) => {
+            let paths = candidates
+                .iter()
+                .filter_map(|path| bookmark_output(path, output.relative.as_ref()));
+            return print_choices(paths.collect(), output.pick.as_deref());
+        }
+        None => {}
     }
 
-    let results = db.query(keywords)?;
+    let results = relativize(db.query_with(keywords, options)?, output.relative.as_ref());
 
-    if suggest {
+    if output.suggest {
         // For suggestion mode, only return a list of paths
         for entry in results.iter().take(10) {
-            println!("{}", entry.path);
+            if output.describe {
+                println!("{}", display::format_suggestion(&entry.path));
+            } else {
+                println!("{}", entry.path);
+            }
         }
     } else {
         // Normal query mode
-        if results.is_empty() {
+        if results.is_empty() && !output.include_bookmarks {
             // Try to provide suggestions
             if let Ok(suggestions) = db.query(&[keyword.chars().take(3).collect()]) {
                 if !suggestions.is_empty() {
-                    eprintln!("{}: No exact match found", "Info".yellow().bold());
+                    eprintln!("{}: No exact match found", theme::warning("Info"));
                     eprintln!("Similar paths:");
                     for (i, entry) in suggestions.iter().take(3).enumerate() {
-                        eprintln!("  {}) {}", i + 1, entry.path.bright_blue());
+                        eprintln!("  {}) {}", i + 1, theme::path(&entry.path));
                     }
                     return Ok(());
                 }
             }
         } else {
-            for entry in results {
-                println!("{}", entry.path);
+            match output.pick.as_deref() {
+                Some(args) if output.group_by_root => {
+                    pick_by_root(db, results, output.format, args)?
+                }
+                _ => {
+                    let lines = result_lines(
+                        db,
+                        &results,
+                        output.format,
+                        output.include_bookmarks,
+                        output.relative.as_ref(),
+                    )?;
+                    print_choices(lines, output.pick.as_deref())?;
+                }
             }
         }
     }
@@ -215,43 +1092,154 @@ fn handle_query(db: &Database, keywords: &[String], suggest: bool) -> Result<()>
     Ok(())
 }
 
+/// Query results formatted with `format`, blended with every bookmark when
+/// `include_bookmarks` is set. `entries` are already relativized; bookmark
+/// targets go through the same `relative` transform so they line up.
+fn result_lines(
+    db: &Database,
+    entries: &[DirEntry],
+    format: &str,
+    include_bookmarks: bool,
+    relative: Option<&Relative>,
+) -> Result<Vec<String>> {
+    if include_bookmarks {
+        let bookmarks: Vec<Bookmark> = db
+            .get_bookmarks()?
+            .into_iter()
+            .filter_map(|bookmark| {
+                let path = bookmark_output(&bookmark.path, relative)?;
+                Some(Bookmark { path, ..bookmark })
+            })
+            .collect();
+        return Ok(display::blend_bookmarks(entries, &bookmarks, format));
+    }
+    Ok(entries
+        .iter()
+        .map(|entry| display::format_entry(format, entry))
+        .collect())
+}
+
+/// Two-stage `--group-by-root` pick: a project root among those of
+/// `entries` with fzf, then one entry under it. A single root skips
+/// straight to the second stage. Exits with 1 if either pick is cancelled.
+fn pick_by_root(
+    db: &Database,
+    entries: Vec<DirEntry>,
+    format: &str,
+    args: &[String],
+) -> Result<()> {
+    let mut groups = db.group_by_root(entries)?;
+    if groups.len() > 1 {
+        let roots: Vec<String> = groups.iter().map(|group| group.root.clone()).collect();
+        let Some(choice) = picker::pick(args, &roots)? else {
+            std::process::exit(1);
+        };
+        groups.retain(|group| group.root == choice);
+    }
+    let Some(group) = groups.pop() else {
+        return Ok(());
+    };
+    let lines = group
+        .members
+        .iter()
+        .map(|entry| display::format_entry(format, entry))
+        .collect();
+    print_choices(lines, Some(args))
+}
+
+/// Prints every line, or only the one picked with fzf when `pick` holds fzf
+/// arguments and there is more than one. Exits with 1 if the pick is cancelled.
+fn print_choices(lines: Vec<String>, pick: Option<&[String]>) -> Result<()> {
+    match pick {
+        Some(args) if lines.len() > 1 => match picker::pick(args, &lines)? {
+            Some(choice) => println!("{}", display::strip_bookmark_annotation(&choice)),
+            None => std::process::exit(1),
+        },
+        _ => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn handle_bookmark(db: &mut Database, action: BookmarkAction) -> Result<()> {
     match action {
-        BookmarkAction::Add { name, path } => {
+        BookmarkAction::Add {
+            name,
+            path,
+            literal,
+            allow_missing,
+            order,
+        } => {
             let target_path = match path {
+                Some(p) if literal => p,
                 Some(p) => shellexpand::tilde(&p).to_string(),
                 None => env::current_dir()?.to_string_lossy().to_string(),
             };
 
-            if !std::path::Path::new(&target_path).exists() {
-                eprintln!(
-                    "{}: Path does not exist: {}",
-                    "Error".red().bold(),
+            match db.add_bookmark_checked(&name, &target_path, allow_missing) {
+                Ok(true) => {}
+                Ok(false) => eprintln!(
+                    "{}: Path does not exist yet: {}",
+                    theme::warning("Warning"),
                     target_path
-                );
-                std::process::exit(1);
+                ),
+                Err(err) => {
+                    eprintln!("{}: {}", theme::error("Error"), err);
+                    std::process::exit(1);
+                }
+            }
+            if order.is_some() {
+                db.set_bookmark_position(&name, order)?;
             }
-
-            db.add_bookmark(&name, &target_path)?;
             println!(
                 "{}: Bookmark '{}' created for {}",
-                "Success".green().bold(),
-                name.bright_yellow(),
-                target_path.bright_blue()
+                theme::success("Success"),
+                theme::name(&name),
+                theme::path(&target_path)
             );
         }
+        BookmarkAction::Alias { existing, name } => {
+            if db.add_bookmark_alias(&existing, &name)? {
+                println!(
+                    "{}: Alias '{}' created for bookmark '{}'",
+                    theme::success("Success"),
+                    theme::name(&name),
+                    theme::name(&existing)
+                );
+            } else {
+                eprintln!(
+                    "{}: Bookmark '{}' not found",
+                    theme::error("Error"),
+                    theme::name(&existing)
+                );
+                std::process::exit(1);
+            }
+        }
         BookmarkAction::Remove { name } => {
             if db.remove_bookmark(&name)? {
                 println!(
                     "{}: Bookmark '{}' removed",
-                    "Success".green().bold(),
-                    name.bright_yellow()
+                    theme::success("Success"),
+                    theme::name(&name)
                 );
             } else {
                 eprintln!(
                     "{}: Bookmark '{}' not found",
-                    "Error".red().bold(),
-                    name.bright_yellow()
+                    theme::error("Error"),
+                    theme::name(&name)
+                );
+                std::process::exit(1);
+            }
+        }
+        BookmarkAction::Reorder { name, position, .. } => {
+            if !db.set_bookmark_position(&name, position)? {
+                eprintln!(
+                    "{}: Bookmark '{}' not found",
+                    theme::error("Error"),
+                    theme::name(&name)
                 );
                 std::process::exit(1);
             }
@@ -261,219 +1249,493 @@ fn handle_bookmark(db: &mut Database, action: BookmarkAction) -> Result<()> {
             if bookmarks.is_empty() {
                 println!("No bookmarks found.");
             } else {
-                println!("{}", "Bookmarks:".bright_green().bold());
+                println!("{}", theme::heading("Bookmarks:"));
                 for bookmark in bookmarks {
-                    println!(
-                        "  {} -> {}",
-                        bookmark.name.bright_yellow(),
-                        bookmark.path.bright_blue()
-                    );
+                    let unverified = if bookmark.is_verified() {
+                        String::new()
+                    } else {
+                        format!(" {}", theme::alert("(unverified)"))
+                    };
+
+                    let position = bookmark
+                        .position
+                        .map_or_else(String::new, |position| format!("{}. ", position));
+
+                    if bookmark.aliases.is_empty() {
+                        println!(
+                            "  {}{} -> {}{}",
+                            position,
+                            theme::name(&bookmark.name),
+                            theme::path(&bookmark.path),
+                            unverified
+                        );
+                    } else {
+                        println!(
+                            "  {}{} -> {} (aliases: {}){}",
+                            position,
+                            theme::name(&bookmark.name),
+                            theme::path(&bookmark.path),
+                            theme::name(bookmark.aliases.join(", ")),
+                            unverified
+                        );
+                    }
                 }
             }
         }
-        BookmarkAction::Get { name } => {
-            if let Some(path) = db.get_bookmark(&name)? {
-                print!("{}", path);
+        BookmarkAction::Stats => {
+            let mut bookmarks = db.get_bookmarks()?;
+            if bookmarks.is_empty() {
+                println!("No bookmarks found.");
+                return Ok(());
+            }
+
+            bookmarks.sort_by(|a, b| {
+                b.use_count
+                    .cmp(&a.use_count)
+                    .then_with(|| b.last_used.cmp(&a.last_used))
+            });
+            println!("{}", theme::heading("Bookmark usage:"));
+            for bookmark in bookmarks {
+                let last_used = bookmark
+                    .last_used
+                    .map_or_else(|| "never".to_string(), |at| display::format_time_ago(&at));
+                println!(
+                    "  {:>5}  {:<12}  {} -> {}",
+                    bookmark.use_count,
+                    last_used,
+                    theme::name(&bookmark.name),
+                    theme::path(&bookmark.path)
+                );
+            }
+        }
+        BookmarkAction::Check { fix } => handle_bookmark_check(db, fix)?,
+        BookmarkAction::Get { name, expand } => {
+            if let Some(path) = db.resolve_bookmark(&name)? {
+                if expand {
+                    print!("{}", nav::expand_path(&path));
+                } else {
+                    print!("{}", path);
+                }
             }
         }
     }
     Ok(())
 }
 
-fn handle_stats(db: &Database) -> Result<()> {
-    let stats = db.get_stats()?;
+fn handle_bookmark_check(db: &mut Database, fix: bool) -> Result<()> {
+    let check = db.check_bookmarks()?;
+    if check.is_clean() {
+        println!("{}: All bookmarks look fine.", theme::success("✓"));
+        return Ok(());
+    }
 
-    println!("{}", "📊 xneo Statistics".bright_green().bold());
-    println!("──────────────────────────────");
-    println!(
-        "Total directories: {}",
-        stats.total_entries.to_string().bright_cyan()
-    );
-    println!(
-        "Total visits: {}",
-        stats.total_visits.to_string().bright_cyan()
-    );
+    for name in &check.invalid {
+        println!(
+            "{}: Bookmark {:?} has an empty name or path",
+            theme::error("Invalid"),
+            name
+        );
+    }
+    for name in &check.missing {
+        println!(
+            "{}: Bookmark '{}' points to a directory that doesn't exist",
+            theme::warning("Missing"),
+            theme::name(name)
+        );
+    }
+    for (target, names) in &check.duplicates {
+        println!(
+            "{}: {} is bookmarked as {}",
+            theme::info("Duplicate"),
+            theme::path(target),
+            theme::name(names.join(", "))
+        );
+    }
 
-    if !stats.most_visited.is_empty() {
-        println!("\n{}", "🔥 Most Visited:".bright_yellow().bold());
-        for (i, entry) in stats.most_visited.iter().enumerate() {
-            println!(
-                "  {}. {} ({} visits)",
-                (i + 1).to_string().bright_white(),
-                entry.path.bright_blue(),
-                entry.visits.to_string().bright_green()
-            );
-        }
+    if check.invalid.is_empty() {
+        return Ok(());
     }
+    if fix {
+        let removed = db.fix_bookmarks()?;
+        println!(
+            "{}: Removed {} invalid bookmarks.",
+            theme::success("✓"),
+            theme::value(removed.to_string())
+        );
+    } else {
+        println!("Run `xneo bookmark check --fix` to remove the invalid bookmarks.");
+    }
+    Ok(())
+}
 
-    if !stats.recently_visited.is_empty() {
-        println!("\n{}", "⏰ Recently Visited:".bright_yellow().bold());
-        for (i, entry) in stats.recently_visited.iter().enumerate() {
-            let time_ago = format_time_ago(&entry.last_access);
-            println!(
-                "  {}. {} ({})",
-                (i + 1).to_string().bright_white(),
-                entry.path.bright_blue(),
-                time_ago.bright_green()
-            );
-        }
+fn handle_stats(db: &Database, watch: bool, interval: u64, top: usize) -> Result<()> {
+    if watch {
+        return handle_stats_watch(db, interval, top);
     }
 
+    let stats = db.get_stats_top(top)?;
     let bookmarks = db.get_bookmarks()?;
-    if !bookmarks.is_empty() {
-        println!("\n{}", "🔖 Bookmarks:".bright_yellow().bold());
-        for bookmark in bookmarks.iter().take(5) {
-            println!(
-                "  {} -> {}",
-                bookmark.name.bright_yellow(),
-                bookmark.path.bright_blue()
-            );
+    print!("{}", display::render_stats(&stats, &bookmarks));
+    Ok(())
+}
+
+fn handle_stats_watch(db: &Database, interval: u64, top: usize) -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_flag = running.clone();
+    ctrlc::set_handler(move || handler_flag.store(false, Ordering::SeqCst))?;
+
+    // Hide the cursor while redrawing
+    print!("\x1b[?25l");
+
+    while running.load(Ordering::SeqCst) {
+        let stats = db.get_stats_top(top)?;
+        let bookmarks = db.get_bookmarks()?;
+        // Clear the screen and move the cursor home before each frame
+        print!("\x1b[2J\x1b[H{}", display::render_stats(&stats, &bookmarks));
+        println!(
+            "\n{}",
+            theme::muted(format!(
+                "Refreshing every {}s. Press Ctrl-C to exit.",
+                interval
+            ))
+        );
+        io::stdout().flush()?;
+
+        // Sleep in short steps so Ctrl-C is handled promptly
+        let deadline = Instant::now() + Duration::from_secs(interval.max(1));
+        while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(100));
         }
-        if bookmarks.len() > 5 {
+    }
+
+    // Restore the cursor
+    print!("\x1b[?25h");
+    io::stdout().flush()?;
+    Ok(())
+}
+
+fn handle_insights(db: &Database, config: &Config) -> Result<()> {
+    let events = db.history_events(None, None)?;
+    let insights =
+        insights::Insights::from_events(&events, &config.expanded_search_roots(), Utc::now());
+    print!("{}", display::render_insights(&insights));
+    Ok(())
+}
+
+/// How many of the newest hook errors `xneo doctor` prints
+const DOCTOR_RECENT_ERRORS: usize = 10;
+
+fn handle_doctor() -> Result<()> {
+    let log = shell::add_log_path().context("Failed to find user's cache directory")?;
+    let contents = match fs::read_to_string(&log) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
             println!(
-                "  ... and {} more",
-                (bookmarks.len() - 5).to_string().bright_cyan()
+                "{}: No hook errors logged (enable logging with `xneo init fish --report-errors`).",
+                theme::info("ℹ")
             );
+            return Ok(());
         }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", log)),
+    };
+
+    let errors: Vec<&str> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    if errors.is_empty() {
+        println!(
+            "{}: The shell hook has logged no errors.",
+            theme::success("✓")
+        );
+        return Ok(());
     }
 
+    println!(
+        "{}: The shell hook logged errors to {} ({} lines); the most recent:",
+        theme::warning("Warning"),
+        theme::path(log.display().to_string()),
+        theme::value(errors.len().to_string())
+    );
+    for line in &errors[errors.len().saturating_sub(DOCTOR_RECENT_ERRORS)..] {
+        println!("  {}", line);
+    }
     Ok(())
 }
 
-fn format_time_ago(datetime: &chrono::DateTime<chrono::Utc>) -> String {
-    let now = chrono::Utc::now();
-    let duration = now.signed_duration_since(*datetime);
+/// Missing directories, plus empty ones with `--prune-empty`
+fn clean_candidates(db: &Database, prune_empty: bool) -> Result<Vec<String>> {
+    let mut candidates = db.find_stale()?;
+    if prune_empty {
+        candidates.extend(db.find_empty()?);
+    }
+    Ok(candidates)
+}
 
-    if duration.num_days() > 0 {
-        format!("{} days ago", duration.num_days())
-    } else if duration.num_hours() > 0 {
-        format!("{} hours ago", duration.num_hours())
-    } else if duration.num_minutes() > 0 {
-        format!("{} minutes ago", duration.num_minutes())
-    } else {
-        "just now".to_string()
+/// Whether two spellings of a path differing only by case are one directory:
+/// paths that exist are compared on disk (`nav::same_file`); otherwise the
+/// kept one's parent is probed with `nav::ignores_case`, remembered per
+/// directory. Case-insensitive macOS/Windows volumes can be mounted next to
+/// case-sensitive ones, so each path is judged by its own filesystem.
+fn case_probe() -> impl FnMut(&Path, &Path) -> bool {
+    let mut probed: HashMap<PathBuf, bool> = HashMap::new();
+    move |kept: &Path, other: &Path| {
+        nav::same_file(kept, other).unwrap_or_else(|| {
+            kept.parent().is_some_and(|dir| {
+                *probed
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| nav::ignores_case(dir))
+            })
+        })
     }
 }
 
-fn handle_clean(db: &mut Database, yes: bool) -> Result<()> {
-    use indicatif::{ProgressBar, ProgressStyle};
+fn handle_clean_porcelain(db: &mut Database, yes: bool, prune_empty: bool) -> Result<()> {
+    // No prompt in porcelain mode: entries are only removed with --yes
+    let merged = if yes {
+        db.merge_case_duplicates(case_probe())?
+    } else {
+        0
+    };
+    let stale_entries = clean_candidates(db, prune_empty)?;
+    let removed = if yes { db.purge(&stale_entries)? } else { 0 };
 
-    println!("{}", "🔍 Scanning for stale entries...".bright_blue());
+    let summary = CleanSummary {
+        stale: stale_entries.len(),
+        removed,
+        merged,
+    };
+    println!("{}", summary.porcelain());
+    io::stdout().flush()?;
+    std::process::exit(summary.exit_code());
+}
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .unwrap(),
-    );
-    pb.set_message("Checking directories...");
+fn handle_clean(db: &mut Database, yes: bool, prune_empty: bool, interactive: bool) -> Result<()> {
+    if !interactive {
+        // Plain status lines for logs
+        colored::control::set_override(false);
+    }
+
+    println!("{}", theme::info("🔍 Scanning for stale entries..."));
+
+    let pb = display::spinner(interactive, "Checking directories...");
 
-    let stale_entries = db.find_stale()?;
+    let mut probe = case_probe();
+    let duplicates = db.count_case_duplicates(&mut probe)?;
+    let stale_entries = clean_candidates(db, prune_empty)?;
     pb.finish_and_clear();
 
-    if stale_entries.is_empty() {
+    if stale_entries.is_empty() && duplicates == 0 {
         println!(
             "{}: Database is clean. No stale entries found.",
-            "✓".green().bold()
+            theme::success("✓")
         );
         return Ok(());
     }
 
-    println!(
-        "{}: Found {} stale entries:",
-        "⚠".yellow().bold(),
-        stale_entries.len().to_string().bright_red()
-    );
+    if duplicates > 0 {
+        println!(
+            "{}: Found {} entries differing only by case from another (their visits are kept).",
+            theme::warning("⚠"),
+            theme::alert(duplicates.to_string())
+        );
+    }
+    if !stale_entries.is_empty() {
+        println!(
+            "{}: Found {} stale entries:",
+            theme::warning("⚠"),
+            theme::alert(stale_entries.len().to_string())
+        );
+    }
 
     for (i, entry) in stale_entries.iter().enumerate() {
         if i < 10 {
-            println!("  - {}", entry.bright_red());
+            println!("  - {}", theme::alert(entry));
         } else if i == 10 {
             println!(
                 "  ... and {} more",
-                (stale_entries.len() - 10).to_string().bright_cyan()
+                theme::value((stale_entries.len() - 10).to_string())
             );
             break;
         }
     }
 
-    let mut confirmed = yes;
-    if !confirmed {
-        print!("\n{} [y/N] ", "Do you want to remove them?".bright_yellow());
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        confirmed = input.trim().eq_ignore_ascii_case("y");
-    }
+    if yes || confirm("Do you want to clean them up?")? {
+        let merged = db.merge_case_duplicates(&mut probe)?;
+        if merged > 0 {
+            println!(
+                "\n{}: Merged {} entries that differed only by case.",
+                theme::success("✓"),
+                theme::value(merged.to_string())
+            );
+        }
+        if stale_entries.is_empty() {
+            return Ok(());
+        }
 
-    if confirmed {
-        let pb = ProgressBar::new(stale_entries.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-                .unwrap(),
-        );
-        pb.set_message("Cleaning...");
+        let pb = display::progress_bar(interactive, stale_entries.len() as u64, "Cleaning...");
 
-        let cleaned_count = db.purge(&stale_entries)?;
+        let cleaned_count =
+            db.purge_with_progress(&stale_entries, |handled| pb.set_position(handled as u64))?;
         pb.finish_with_message("Done!");
 
         println!(
             "\n{}: Successfully removed {} stale entries.",
-            "✓".green().bold(),
-            cleaned_count.to_string().bright_green()
+            theme::success("✓"),
+            theme::value(cleaned_count.to_string())
         );
     } else {
-        println!("\n{}: No changes were made.", "ℹ".blue().bold());
+        println!("\n{}: No changes were made.", theme::info("ℹ"));
     }
 
     Ok(())
 }
 
+/// Asks a yes/no question on stdin; anything but `y` means no
+fn confirm(question: &str) -> Result<bool> {
+    print!("\n{} [y/N] ", theme::warning(question));
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
 fn handle_config(config: &Config, action: Option<ConfigAction>) -> Result<()> {
     match action {
         Some(ConfigAction::Show) | None => {
-            println!("{}", "🔧 xneo Configuration".bright_green().bold());
+            println!("{}", theme::heading("🔧 xneo Configuration"));
             println!("──────────────────────────────");
             println!(
                 "Max entries: {}",
-                config.max_entries.to_string().bright_cyan()
+                theme::value(config.max_entries.to_string())
             );
             println!(
                 "Update threshold: {} hours",
-                config.update_threshold_hours.to_string().bright_cyan()
+                theme::value(config.update_threshold_hours.to_string())
             );
             println!(
                 "Fuzzy matching: {}",
-                if config.enable_fuzzy_matching {
-                    "enabled".green()
-                } else {
-                    "disabled".red()
-                }
+                theme::toggle(config.enable_fuzzy_matching)
+            );
+            println!(
+                "Fuzzy case sensitive: {}",
+                theme::toggle(config.fuzzy_case_sensitive)
+            );
+            println!(
+                "Min fuzzy query length: {}",
+                theme::value(config.min_fuzzy_query_len.to_string())
+            );
+            println!(
+                "Substring at word start: {}",
+                theme::toggle(config.substring_at_word_start)
             );
             println!(
                 "Auto clean on startup: {}",
-                if config.auto_clean_on_startup {
-                    "enabled".green()
-                } else {
-                    "disabled".red()
-                }
+                theme::toggle(config.auto_clean_on_startup)
+            );
+            println!("FZF options: {}", theme::path(&config.fzf_options));
+            if let Some(command) = &config.post_jump_command {
+                println!("Post-jump command: {}", theme::path(command));
+            }
+            if let Some(height) = &config.picker_height {
+                println!("Picker height: {}", theme::value(height));
+            }
+            if let Some(command) = &config.picker_preview_command {
+                println!("Picker preview command: {}", theme::path(command));
+            }
+
+            if !config.query_aliases.is_empty() {
+                let mut aliases: Vec<_> = config.query_aliases.iter().collect();
+                aliases.sort();
+                let aliases: Vec<String> = aliases
+                    .into_iter()
+                    .map(|(alias, keyword)| format!("{alias} -> {keyword}"))
+                    .collect();
+                println!("Query aliases: {}", theme::path(aliases.join(", ")));
+            }
+            if !config.search_roots.is_empty() {
+                println!(
+                    "Search roots: {}",
+                    theme::path(config.search_roots.join(", "))
+                );
+            }
+            if let Some(ratio) = config.auto_select_if_dominant {
+                println!(
+                    "Auto-select if dominant: {}x",
+                    theme::value(ratio.to_string())
+                );
+            }
+            if config.min_path_components > 0 {
+                println!(
+                    "Min path components: {}",
+                    theme::value(config.min_path_components.to_string())
+                );
+            }
+            println!(
+                "Max query length: {}",
+                theme::value(config.max_query_len.to_string())
+            );
+            println!(
+                "Purge batch size: {}",
+                theme::value(config.purge_batch_size.to_string())
+            );
+            if let Some(depth) = config.max_track_depth_from_root {
+                println!(
+                    "Max track depth from root: {}",
+                    theme::value(depth.to_string())
+                );
+            }
+
+            println!("Ignore hidden: {}", theme::toggle(config.ignore_hidden));
+            println!("Ignore home dir: {}", theme::toggle(config.ignore_home_dir));
+            println!(
+                "Record symlink aliases: {}",
+                theme::toggle(config.record_symlink_aliases)
+            );
+            println!("Buffer adds: {}", theme::toggle(config.buffer_adds));
+            println!(
+                "Theme: {}",
+                theme::path(format!("{:?}", config.theme).to_lowercase())
+            );
+            println!(
+                "Bare invocation: {}",
+                theme::path(format!("{:?}", config.bare_invocation).to_lowercase())
+            );
+            if let Some(dir) = &config.default_dir {
+                println!("Default directory: {}", theme::path(dir));
+            }
+            println!(
+                "Bookmark case insensitive: {}",
+                theme::toggle(config.bookmark_case_insensitive)
             );
-            println!("FZF options: {}", config.fzf_options.bright_blue());
+            println!("Use $CDPATH: {}", theme::toggle(config.use_cdpath));
+            println!("Use mtime: {}", theme::toggle(config.use_mtime));
+            println!("Prefer git root: {}", theme::toggle(config.prefer_git_root));
+
+            if !config.skip_if_env_set.is_empty() {
+                println!(
+                    "Skip recording when set: {}",
+                    theme::path(config.skip_if_env_set.join(", "))
+                );
+            }
 
             if !config.ignored_patterns.is_empty() {
-                println!("\n{}", "🚫 Ignored patterns:".bright_yellow().bold());
+                println!("\n{}", theme::section("🚫 Ignored patterns:"));
                 for pattern in &config.ignored_patterns {
-                    println!("  - {}", pattern.bright_red());
+                    println!("  - {}", theme::alert(pattern));
                 }
             }
         }
         Some(ConfigAction::Edit) => {
-            let config_path = dirs::config_dir().unwrap().join("xneo").join("config.json");
+            let config_path = Config::config_path()?;
 
             println!(
                 "Opening config file: {}",
-                config_path.display().to_string().bright_blue()
+                theme::path(config_path.display().to_string())
             );
 
             let editors = ["code", "vim", "nano", "emacs", "notepad"];
@@ -490,24 +1752,58 @@ fn handle_config(config: &Config, action: Option<ConfigAction>) -> Result<()> {
             }
 
             if !opened {
-                println!("{}: Could not find a suitable editor", "Error".red().bold());
+                println!(
+                    "{}: Could not find a suitable editor",
+                    theme::error("Error")
+                );
                 println!("Please edit the file manually: {}", config_path.display());
             }
         }
-        Some(ConfigAction::Reset) => {
-            let new_config = Config::default();
-            new_config.save()?;
-            println!("{}: Configuration reset to defaults", "✓".green().bold());
+        Some(ConfigAction::Reset { yes }) => {
+            let reset =
+                Config::reset(|| Ok(yes || confirm("Reset the configuration to defaults?")?))?;
+
+            match reset {
+                ConfigReset::Done { backup } => {
+                    println!("{}: Configuration reset to defaults", theme::success("✓"));
+                    if let Some(backup) = backup {
+                        println!(
+                            "Previous configuration saved to {}",
+                            theme::path(backup.display().to_string())
+                        );
+                    }
+                }
+                ConfigReset::Declined => {
+                    println!("\n{}: No changes were made.", theme::info("ℹ"));
+                }
+            }
+        }
+        Some(ConfigAction::Diff) => {
+            let changes = config.diff_from_default()?;
+            if changes.is_empty() {
+                println!("All settings are at their defaults.");
+            }
+            for (field, default, current) in changes {
+                println!(
+                    "{}: {} -> {}",
+                    theme::name(&field),
+                    theme::muted(default.to_string()),
+                    theme::value(current.to_string())
+                );
+            }
+        }
+        Some(ConfigAction::Path) => {
+            println!("Config: {}", Config::config_path()?.display());
+            println!("Database: {}", Database::db_path()?.display());
         }
         // New: handle get command
         Some(ConfigAction::Get { key }) => match key.as_str() {
             "fzf_options" => print!("{}", config.fzf_options),
             _ => {
-                eprintln!("{}: Unknown config key: {}", "Error".red().bold(), key);
+                eprintln!("{}: Unknown config key: {}", theme::error("Error"), key);
                 std::process::exit(1);
             }
         },
     }
     Ok(())
 }
-
@@ -0,0 +1,182 @@
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+/// Whether `keyword` is made only of dots (`..`, `...`, `....`), a
+/// relative jump handled by `resolve_dots`
+pub fn is_dots(keyword: &str) -> bool {
+    keyword.len() >= 2 && keyword.chars().all(|c| c == '.')
+}
+
+/// Resolves a keyword made only of dots (`..`, `...`, `....`) relative to `cwd`.
+/// N dots go up N-1 levels, stopping at the filesystem root.
+pub fn resolve_dots(keyword: &str, cwd: &Path) -> Option<PathBuf> {
+    if !is_dots(keyword) {
+        return None;
+    }
+
+    let levels = keyword.len() - 1;
+    let target = cwd
+        .ancestors()
+        .nth(levels)
+        .or_else(|| cwd.ancestors().last())?;
+
+    Some(target.to_path_buf())
+}
+
+/// Finds an ancestor of `cwd` (or `cwd` itself) whose directory name is `name`.
+///
+/// Only `max_depth` levels above `cwd` are considered when given. The nearest
+/// match is returned unless `farthest` asks for the topmost one.
+pub fn find_ancestor(
+    name: &str,
+    cwd: &Path,
+    max_depth: Option<usize>,
+    farthest: bool,
+) -> Option<PathBuf> {
+    let levels = max_depth.map_or(usize::MAX, |depth| depth.saturating_add(1));
+    let mut matches = cwd
+        .ancestors()
+        .take(levels)
+        .filter(|ancestor| ancestor.file_name().and_then(|s| s.to_str()) == Some(name));
+
+    let found = if farthest {
+        matches.last()
+    } else {
+        matches.next()
+    };
+    found.map(Path::to_path_buf)
+}
+
+/// Canonical form of a stored or looked-up path: `/` separators (see
+/// `normalize_separators`), no repeated `/` and no trailing `/` except on a
+/// root (`/`, `C:/`). The leading `//` of a UNC path is kept.
+pub fn normalize_path(path: &str) -> String {
+    let unc = path.starts_with("\\\\");
+    let mut normalized = String::with_capacity(path.len());
+    if unc {
+        normalized.push('/');
+    }
+    for c in normalize_separators(path).chars() {
+        if c == '/' && normalized.ends_with('/') && !(unc && normalized.len() == 1) {
+            continue;
+        }
+        normalized.push(c);
+    }
+
+    let is_root = |p: &str| p == "/" || p == "//" || (p.len() == 3 && p.ends_with(":/"));
+    while normalized.ends_with('/') && !is_root(&normalized) {
+        normalized.pop();
+    }
+    normalized
+}
+
+/// `path` made absolute against `cwd` and normalized, with `.` and `..`
+/// components of a relative path resolved lexically (`../api` from
+/// `/work/web` is `/work/api`). Absolute paths, Windows ones included, are
+/// only normalized.
+pub fn absolute_path(path: &str, cwd: &Path) -> String {
+    let normalized = normalize_path(path);
+    if is_absolute(&normalized) {
+        return normalized;
+    }
+
+    let mut resolved = cwd.to_path_buf();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    normalize_path(&resolved.to_string_lossy())
+}
+
+/// Whether `path` is absolute on Unix or Windows (`/srv`, `C:/Users`,
+/// `//server/share`) regardless of the platform xneo runs on
+pub fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    path.starts_with(['/', '\\']) || drive || Path::new(path).is_absolute()
+}
+
+/// Rewrites Windows paths (`C:\Users\me`, `\\server\share`) with `/` separators,
+/// the canonical form used for storage, matching and ignore globs. Other
+/// paths are returned unchanged since `\` is a valid Unix file name character.
+pub fn normalize_separators(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let drive_prefix = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    let unc_prefix = path.starts_with("\\\\");
+
+    if cfg!(windows) || drive_prefix || unc_prefix {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+/// `path` relative to `base` (`.` for `base` itself), or `None` when it
+/// isn't inside `base`. Compares whole components, so `/work2` isn't under
+/// `/work`.
+pub fn relative_to(path: &str, base: &Path) -> Option<String> {
+    let relative = Path::new(path).strip_prefix(base).ok()?;
+    if relative.as_os_str().is_empty() {
+        Some(".".to_string())
+    } else {
+        Some(normalize_separators(&relative.to_string_lossy()))
+    }
+}
+
+/// Existing `<dir>/<keyword>` directories for each `dir` in a
+/// colon-separated `$CDPATH` value, in `$CDPATH` order.
+pub fn cdpath_matches(keyword: &str, cdpath: &OsStr) -> Vec<PathBuf> {
+    std::env::split_paths(cdpath)
+        // An empty entry means the current directory, which `x` already tries
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(|dir| dir.join(keyword))
+        .filter(|candidate| candidate.is_dir())
+        .collect()
+}
+
+/// Expands `~` and environment variables in a stored path.
+/// Falls back to the path as-is if a variable is undefined.
+pub fn expand_path(path: &str) -> String {
+    shellexpand::full(path)
+        .map(|expanded| expanded.into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Whether `a` and `b` are the same file or directory: same device and inode
+/// on unix, same canonical path elsewhere. `None` if either can't be read.
+pub fn same_file(a: &Path, b: &Path) -> Option<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let (a, b) = (std::fs::metadata(a).ok()?, std::fs::metadata(b).ok()?);
+        Some(a.dev() == b.dev() && a.ino() == b.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        Some(std::fs::canonicalize(a).ok()? == std::fs::canonicalize(b).ok()?)
+    }
+}
+
+/// Whether the filesystem holding `dir` ignores case (macOS and Windows by
+/// default). Probed by creating a mixed-case file in `dir` and looking it up
+/// in lowercase; `false` if `dir` isn't writable. Prefer `same_file` on
+/// paths that exist, which writes nothing.
+pub fn ignores_case(dir: &Path) -> bool {
+    let probe = dir.join(format!(".XneoCaseProbe{}", std::process::id()));
+    if std::fs::write(&probe, b"").is_err() {
+        return false;
+    }
+
+    let lowered = dir.join(format!(".xneocaseprobe{}", std::process::id()));
+    let ignores = lowered.exists();
+    let _ = std::fs::remove_file(&probe);
+    ignores
+}
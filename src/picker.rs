@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+
+/// Preview shown for `--preview-lines` when no `picker_preview_command` is set
+pub const DEFAULT_PREVIEW_COMMAND: &str = "ls -1 {}";
+
+/// Arguments for the fzf picker behind `xneo query --pick`.
+///
+/// `fzf_options` is split on whitespace and comes first, so the dedicated
+/// `picker_*` settings override anything it sets.
+pub fn fzf_args(config: &Config, preview_lines: Option<usize>) -> Vec<String> {
+    let mut args: Vec<String> = config
+        .fzf_options
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+
+    if let Some(height) = &config.picker_height {
+        args.push(format!("--height={}", height));
+    }
+
+    let preview = config
+        .picker_preview_command
+        .as_deref()
+        .or(preview_lines.map(|_| DEFAULT_PREVIEW_COMMAND));
+    if let Some(command) = preview {
+        args.push(format!("--preview={}", command));
+        if let Some(lines) = preview_lines {
+            args.push(format!("--preview-window=down:{}", lines));
+        }
+    }
+
+    args.push("--prompt=Select directory: ".to_string());
+    args
+}
+
+/// Lets the user choose one of `candidates` with fzf, or with a numbered
+/// prompt on stderr when fzf isn't installed. Returns `None` when the
+/// selection is cancelled; fzf failing for any other reason is an error.
+pub fn pick(args: &[String], candidates: &[String]) -> Result<Option<String>> {
+    pick_with(
+        "fzf",
+        args,
+        candidates,
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+        fallback_hint_marker().as_deref(),
+    )
+}
+
+/// Created once the "fzf not found" hint has been shown, so it shows once
+fn fallback_hint_marker() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("xneo").join("fzf-hint-shown"))
+}
+
+/// `pick` with the picker program, the numbered prompt's input and output,
+/// and the hint marker (`None` to always show the hint) passed in
+pub fn pick_with(
+    program: &str,
+    args: &[String],
+    candidates: &[String],
+    prompt_in: &mut impl BufRead,
+    prompt_out: &mut impl Write,
+    hint_marker: Option<&Path>,
+) -> Result<Option<String>> {
+    let spawned = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if !hint_marker.is_some_and(Path::exists) {
+                writeln!(
+                    prompt_out,
+                    "Hint: {} not found; install it for fuzzy selection. Using a numbered list.",
+                    program
+                )?;
+                if let Some(marker) = hint_marker {
+                    // Best effort: at worst the hint shows again
+                    let _ = marker.parent().map(std::fs::create_dir_all);
+                    let _ = std::fs::write(marker, b"");
+                }
+            }
+            return prompt_numbered(candidates, prompt_in, prompt_out);
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to run {}", program)),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(candidates.join("\n").as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    match output.status.code() {
+        Some(0) => {
+            let choice = String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string();
+            Ok(Some(choice).filter(|choice| !choice.is_empty()))
+        }
+        // 1: nothing matched the filter, 130: cancelled with Esc or Ctrl-C
+        Some(1) | Some(130) => Ok(None),
+        _ => anyhow::bail!("{} exited with {}", program, output.status),
+    }
+}
+
+/// Lists `candidates` numbered from 1 and reads the chosen number. An empty
+/// answer, end of input or a number out of range cancels.
+fn prompt_numbered(
+    candidates: &[String],
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<String>> {
+    for (i, candidate) in candidates.iter().enumerate() {
+        writeln!(output, "  {}) {}", i + 1, candidate)?;
+    }
+    write!(output, "Select directory [1-{}]: ", candidates.len())?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| candidates.get(i))
+        .cloned())
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+/// Kind of project a directory holds, told apart by marker files in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProjectType {
+    Rust,
+    Node,
+    Python,
+    Git,
+}
+
+impl ProjectType {
+    pub const ALL: [ProjectType; 4] = [
+        ProjectType::Rust,
+        ProjectType::Node,
+        ProjectType::Python,
+        ProjectType::Git,
+    ];
+
+    /// Name stored in `dirs.project_type` and accepted by `query --type`
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectType::Rust => "rust",
+            ProjectType::Node => "node",
+            ProjectType::Python => "python",
+            ProjectType::Git => "git",
+        }
+    }
+
+    /// Entries directly inside a directory that mark it as this type
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            ProjectType::Rust => &["Cargo.toml"],
+            ProjectType::Node => &["package.json"],
+            ProjectType::Python => &["pyproject.toml", "setup.py", "requirements.txt"],
+            ProjectType::Git => &[".git"],
+        }
+    }
+}
+
+/// Comma-separated names of every type `dir` is a project of (`rust,git`),
+/// as stored in `dirs.project_type`, or `None` for a plain directory.
+/// Only checks a handful of paths, so it is cheap enough for every `add`.
+pub fn detect(dir: &Path) -> Option<String> {
+    let types: Vec<&str> = ProjectType::ALL
+        .into_iter()
+        .filter(|kind| {
+            kind.markers()
+                .iter()
+                .any(|marker| dir.join(marker).exists())
+        })
+        .map(ProjectType::name)
+        .collect();
+    (!types.is_empty()).then(|| types.join(","))
+}
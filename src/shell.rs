@@ -1,25 +1,41 @@
+use std::path::{Path, PathBuf};
+
+/// Line in each init script replaced by the configured `post_jump_command`
+pub const POST_JUMP_MARKER: &str = "# xneo:post_jump";
+
+/// The fish hook's call to `xneo add`, redirected by `with_add_log`
+const FISH_ADD_HOOK: &str = r#"command xneo add "$PWD" &"#;
+
 pub const FISH_INIT_SCRIPT: &str = r#"
+# Every jump goes through here so the post_jump_command hook runs after it
+if not functions -q __xneo_cd
+    function __xneo_cd
+        cd $argv; or return
+        # xneo:post_jump
+    end
+end
+
 # Prevents this from being defined more than once
 if not functions -q x
     function x --wraps cd --description "A smarter cd command powered by xneo"
         # Case 1: No arguments. Go home.
         if test -z "$argv"
-            cd (command xneo)
+            __xneo_cd (command xneo)
             return
         end
 
         # Case 2: Check if it's a bookmark
         if test (count $argv) -eq 1
-            set -l bookmark_path (command xneo bookmark get "$argv[1]" 2>/dev/null)
+            set -l bookmark_path (command xneo bookmark get --expand "$argv[1]" 2>/dev/null)
             if test -n "$bookmark_path"
-                cd "$bookmark_path"
+                __xneo_cd "$bookmark_path"
                 return
             end
         end
 
         # Case 3: Argument is a direct, valid path. HIGHEST priority.
         if test -d "$argv[1]"
-            cd "$argv[1]"
+            __xneo_cd "$argv[1]"
             return
         end
         
@@ -31,16 +47,16 @@ if not functions -q x
 
         # If an ancestor match was found, jump there
         if test -n "$ancestor_path"
-            cd "$ancestor_path"
+            __xneo_cd "$ancestor_path"
         else
             # Case 5: Global Database Query
-            set -l results (command xneo query $argv | string split -n '\n')
+            set -l results (command xneo query -- $argv | string split -n '\n')
             set -l count (count $results)
             
             if test $count -eq 0
                 echo "x: No match found for: $argv" >&2
                 # Show similar suggestions
-                set -l suggestions (command xneo query --suggest $argv 2>/dev/null | string split -n '\n')
+                set -l suggestions (command xneo query --suggest -- $argv 2>/dev/null | string split -n '\n')
                 if test (count $suggestions) -gt 0
                     echo "Did you mean:" >&2
                     for suggestion in $suggestions[1..3]
@@ -49,13 +65,13 @@ if not functions -q x
                 end
                 return 1
             else if test $count -eq 1
-                cd "$results[1]"
+                __xneo_cd "$results[1]"
             else
-                # Update: dynamically get fzf configuration
+                # Read fzf_options on every call so config edits apply without re-sourcing
                 set -l fzf_opts (command xneo config get fzf_options)
                 set -l choice (printf "%s\n" $results | string replace -r "^$HOME" "~" | eval "fzf $fzf_opts --prompt=\"Select directory: \"")
                 if test -n "$choice"
-                    cd (string replace -r "^~" $HOME $choice)
+                    __xneo_cd (string replace -r "^~" $HOME $choice)
                 else
                     return 1
                 end
@@ -64,6 +80,17 @@ if not functions -q x
     end
 end
 
+# Completion: directory names, with the full path as the description
+if not functions -q __xneo_complete
+    function __xneo_complete
+        set -l token (commandline -ct)
+        if test -n "$token"
+            command xneo query --suggest --describe -- $token 2>/dev/null
+        end
+    end
+end
+complete -c x -f -a '(__xneo_complete)'
+
 # History recording hook
 if not functions -q __xneo_add_hook
     function __xneo_add_hook --on-variable PWD
@@ -82,26 +109,32 @@ end
 pub const BASH_INIT_SCRIPT: &str = r#"
 # xneo initialization for Bash
 
+# Every jump goes through here so the post_jump_command hook runs after it
+__xneo_cd() {
+    cd "$@" || return
+    # xneo:post_jump
+}
+
 x() {
     # Case 1: No arguments, go home
     if [[ $# -eq 0 ]]; then
-        cd "$(command xneo)"
+        __xneo_cd "$(command xneo)"
         return
     fi
 
     # Case 2: Check if it's a bookmark
     if [[ $# -eq 1 ]]; then
         local bookmark_path
-        bookmark_path=$(command xneo bookmark get "$1" 2>/dev/null)
+        bookmark_path=$(command xneo bookmark get --expand "$1" 2>/dev/null)
         if [[ -n "$bookmark_path" ]]; then
-            cd "$bookmark_path"
+            __xneo_cd "$bookmark_path"
             return
         fi
     fi
 
     # Case 3: Direct path exists
     if [[ -d "$1" ]]; then
-        cd "$1"
+        __xneo_cd "$1"
         return
     fi
 
@@ -110,21 +143,21 @@ x() {
         local ancestor_path
         ancestor_path=$(command xneo query --ancestor "$1" 2>/dev/null)
         if [[ -n "$ancestor_path" ]]; then
-            cd "$ancestor_path"
+            __xneo_cd "$ancestor_path"
             return
         fi
     fi
 
     # Case 5: Database query
     local results
-    mapfile -t results < <(command xneo query "$@")
+    mapfile -t results < <(command xneo query -- "$@")
     
     case ${#results[@]} in
         0)
             echo "x: No match found for: $*" >&2
             # Show suggestions
             local suggestions
-            mapfile -t suggestions < <(command xneo query --suggest "$@" 2>/dev/null)
+            mapfile -t suggestions < <(command xneo query --suggest -- "$@" 2>/dev/null)
             if [[ ${#suggestions[@]} -gt 0 ]]; then
                 echo "Did you mean:" >&2
                 printf "  %s\n" "${suggestions[@]:0:3}" >&2
@@ -132,16 +165,16 @@ x() {
             return 1
             ;;
         1)
-            cd "${results[0]}"
+            __xneo_cd "${results[0]}"
             ;;
         *)
-            # Update: dynamically get fzf configuration
+            # Read fzf_options on every call so config edits apply without re-sourcing
             local fzf_opts
             fzf_opts=$(command xneo config get fzf_options)
             local choice
             choice=$(printf "%s\n" "${results[@]}" | sed "s|^$HOME|~|" | eval "fzf $fzf_opts --prompt=\"Select directory: \"")
             if [[ -n "$choice" ]]; then
-                cd "${choice/#\~/$HOME}"
+                __xneo_cd "${choice/#\~/$HOME}"
             else
                 return 1
             fi
@@ -168,7 +201,7 @@ xb() {
 _x_completion() {
     local cur="${COMP_WORDS[COMP_CWORD]}"
     local suggestions
-    mapfile -t suggestions < <(command xneo query --suggest "$cur" 2>/dev/null)
+    mapfile -t suggestions < <(command xneo query --suggest -- "$cur" 2>/dev/null)
     COMPREPLY=($(compgen -W "${suggestions[*]}" -- "$cur"))
 }
 
@@ -178,26 +211,32 @@ complete -F _x_completion x
 pub const ZSH_INIT_SCRIPT: &str = r#"
 # xneo initialization for Zsh
 
+# Every jump goes through here so the post_jump_command hook runs after it
+__xneo_cd() {
+    cd "$@" || return
+    # xneo:post_jump
+}
+
 x() {
     # Case 1: No arguments, go home
     if [[ $# -eq 0 ]]; then
-        cd "$(command xneo)"
+        __xneo_cd "$(command xneo)"
         return
     fi
 
     # Case 2: Check if it's a bookmark
     if [[ $# -eq 1 ]]; then
         local bookmark_path
-        bookmark_path=$(command xneo bookmark get "$1" 2>/dev/null)
+        bookmark_path=$(command xneo bookmark get --expand "$1" 2>/dev/null)
         if [[ -n "$bookmark_path" ]]; then
-            cd "$bookmark_path"
+            __xneo_cd "$bookmark_path"
             return
         fi
     fi
 
     # Case 3: Direct path exists
     if [[ -d "$1" ]]; then
-        cd "$1"
+        __xneo_cd "$1"
         return
     fi
 
@@ -206,21 +245,21 @@ x() {
         local ancestor_path
         ancestor_path=$(command xneo query --ancestor "$1" 2>/dev/null)
         if [[ -n "$ancestor_path" ]]; then
-            cd "$ancestor_path"
+            __xneo_cd "$ancestor_path"
             return
         fi
     fi
 
     # Case 5: Database query
     local results
-    results=(${(f)"$(command xneo query "$@")"})
+    results=(${(f)"$(command xneo query -- "$@")"})
     
     case ${#results[@]} in
         0)
             echo "x: No match found for: $*" >&2
             # Show suggestions
             local suggestions
-            suggestions=(${(f)"$(command xneo query --suggest "$@" 2>/dev/null)"})
+            suggestions=(${(f)"$(command xneo query --suggest -- "$@" 2>/dev/null)"})
             if [[ ${#suggestions[@]} -gt 0 ]]; then
                 echo "Did you mean:" >&2
                 printf "  %s\n" "${suggestions[@]:0:3}" >&2
@@ -228,16 +267,16 @@ x() {
             return 1
             ;;
         1)
-            cd "${results[1]}"
+            __xneo_cd "${results[1]}"
             ;;
         *)
-            # Update: dynamically get fzf configuration
+            # Read fzf_options on every call so config edits apply without re-sourcing
             local fzf_opts
             fzf_opts=$(command xneo config get fzf_options)
             local choice
             choice=$(printf "%s\n" "${results[@]}" | sed "s|^$HOME|~|" | fzf ${(z)fzf_opts} --prompt="Select directory: ")
             if [[ -n "$choice" ]]; then
-                cd "${choice/#\~/$HOME}"
+                __xneo_cd "${choice/#\~/$HOME}"
             else
                 return 1
             fi
@@ -260,3 +299,231 @@ xb() {
 
 "#;
 
+pub const POWERSHELL_INIT_SCRIPT: &str = r#"
+# xneo initialization for PowerShell
+
+# Every jump goes through here so the post_jump_command hook runs after it
+function __xneo_cd([string]$Path) {
+    Set-Location -LiteralPath $Path -ErrorAction Stop
+    # xneo:post_jump
+}
+
+function x {
+    # Case 1: No arguments, go home
+    if ($args.Count -eq 0) {
+        __xneo_cd (& xneo)
+        return
+    }
+    $query = @($args | ForEach-Object { "$_" })
+
+    # Case 2: Check if it's a bookmark
+    if ($query.Count -eq 1) {
+        $bookmark_path = & xneo bookmark get --expand $query[0] 2>$null
+        if ($bookmark_path) {
+            __xneo_cd $bookmark_path
+            return
+        }
+    }
+
+    # Case 3: Direct path exists
+    if (Test-Path -LiteralPath $query[0] -PathType Container) {
+        __xneo_cd $query[0]
+        return
+    }
+
+    # Case 4: Context-aware ancestor matching (now handled by Rust)
+    if ($query.Count -eq 1) {
+        $ancestor_path = & xneo query --ancestor $query[0] 2>$null
+        if ($ancestor_path) {
+            __xneo_cd $ancestor_path
+            return
+        }
+    }
+
+    # Case 5: Database query
+    $results = @(& xneo query -- @query)
+    switch ($results.Count) {
+        0 {
+            [Console]::Error.WriteLine("x: No match found for: $query")
+            # Show suggestions
+            $suggestions = @(& xneo query --suggest -- @query 2>$null)
+            if ($suggestions.Count -gt 0) {
+                [Console]::Error.WriteLine("Did you mean:")
+                $suggestions | Select-Object -First 3 | ForEach-Object {
+                    [Console]::Error.WriteLine("  $_")
+                }
+            }
+            return
+        }
+        1 {
+            __xneo_cd $results[0]
+        }
+        default {
+            # xneo runs fzf itself (or a numbered prompt without it)
+            $choice = & xneo query --pick -- @query
+            if ($LASTEXITCODE -eq 0 -and $choice) {
+                __xneo_cd $choice
+            }
+        }
+    }
+}
+
+# History recording: the prompt records each directory it is drawn in, once
+# per change, wrapping whatever prompt was defined before
+if (-not (Test-Path Function:\__xneo_prompt)) {
+    $global:__xneo_last_pwd = $null
+    ${function:global:__xneo_prompt} = $function:prompt
+    function global:prompt {
+        if ($PWD.Provider.Name -eq 'FileSystem' -and $PWD.ProviderPath -ne $global:__xneo_last_pwd) {
+            $global:__xneo_last_pwd = $PWD.ProviderPath
+            & xneo add $PWD.ProviderPath 2>$null
+        }
+        & $function:__xneo_prompt
+    }
+}
+
+# Bookmark alias
+function xb {
+    & xneo bookmark @args
+}
+"#;
+
+pub const FISH_WIDGET_SCRIPT: &str = r#"
+# xneo widget for fish: Ctrl-G picks one of the top directories with fzf and
+# jumps there, or inserts it at the cursor when a command is being typed
+function __xneo_widget
+    set -l dir (command xneo query --top --pick </dev/tty)
+    if test -n "$dir"
+        if test -n "$(commandline)"
+            commandline -i -- (string escape -- $dir)
+        else if functions -q __xneo_cd
+            __xneo_cd $dir
+        else
+            cd $dir
+        end
+    end
+    commandline -f repaint
+end
+bind \cg __xneo_widget
+"#;
+
+pub const BASH_WIDGET_SCRIPT: &str = r#"
+# xneo widget for Bash: Ctrl-G picks one of the top directories with fzf and
+# jumps there, or inserts it at the cursor when a command is being typed
+__xneo_widget() {
+    local dir
+    dir=$(command xneo query --top --pick) || return
+    [[ -n "$dir" ]] || return
+    if [[ -n "$READLINE_LINE" ]]; then
+        local quoted
+        quoted=$(printf '%q' "$dir")
+        READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$quoted${READLINE_LINE:READLINE_POINT}"
+        READLINE_POINT=$((READLINE_POINT + ${#quoted}))
+    else
+        if declare -F __xneo_cd >/dev/null; then
+            __xneo_cd "$dir"
+        else
+            cd -- "$dir"
+        fi
+        # PROMPT_COMMAND doesn't run for bind -x; record the jump directly
+        if declare -F __xneo_add_hook >/dev/null; then
+            __xneo_add_hook
+        fi
+    fi
+}
+bind -x '"\C-g": __xneo_widget'
+"#;
+
+pub const ZSH_WIDGET_SCRIPT: &str = r#"
+# xneo widget for Zsh: Ctrl-G picks one of the top directories with fzf and
+# jumps there, or inserts it at the cursor when a command is being typed
+__xneo_widget() {
+    local dir
+    dir=$(command xneo query --top --pick </dev/tty)
+    if [[ -n "$dir" ]]; then
+        if [[ -n "$BUFFER" ]]; then
+            LBUFFER+="${(q)dir}"
+        else
+            if (( $+functions[__xneo_cd] )); then
+                __xneo_cd "$dir"
+            else
+                cd -- "$dir"
+            fi
+            # precmd doesn't run for widgets; record the jump directly
+            if (( $+functions[__xneo_add_hook] )); then
+                __xneo_add_hook
+            fi
+        fi
+    fi
+    zle reset-prompt
+}
+zle -N __xneo_widget
+bindkey '^G' __xneo_widget
+"#;
+
+/// The Ctrl-G widget script for `xneo completions <shell> --widget`
+pub fn widget_script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "fish" => Some(FISH_WIDGET_SCRIPT),
+        "bash" => Some(BASH_WIDGET_SCRIPT),
+        "zsh" => Some(ZSH_WIDGET_SCRIPT),
+        _ => None,
+    }
+}
+
+/// The init script for `shell`, before `with_post_jump` and friends
+pub fn init_script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "fish" => Some(FISH_INIT_SCRIPT),
+        "bash" => Some(BASH_INIT_SCRIPT),
+        "zsh" => Some(ZSH_INIT_SCRIPT),
+        "powershell" => Some(POWERSHELL_INIT_SCRIPT),
+        _ => None,
+    }
+}
+
+/// The rc file `shell` reads at startup (with `~` for the home directory)
+/// and the line that loads xneo from it, for `xneo init <shell> --print-path`
+pub fn rc_snippet(shell: &str) -> Option<(&'static str, &'static str)> {
+    match shell {
+        "fish" => Some(("~/.config/fish/config.fish", "xneo init fish | source")),
+        "bash" => Some(("~/.bashrc", r#"eval "$(xneo init bash)""#)),
+        "zsh" => Some(("~/.zshrc", r#"eval "$(xneo init zsh)""#)),
+        "powershell" => Some((
+            if cfg!(windows) {
+                "~/Documents/PowerShell/Microsoft.PowerShell_profile.ps1"
+            } else {
+                "~/.config/powershell/Microsoft.PowerShell_profile.ps1"
+            },
+            "Invoke-Expression (& xneo init powershell | Out-String)",
+        )),
+        _ => None,
+    }
+}
+
+/// Where `xneo init fish --report-errors` makes the hook log failed adds
+pub fn add_log_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("xneo").join("add.log"))
+}
+
+/// The fish `script` with the hook's `xneo add` errors appended to `log`
+/// instead of the terminal, for `xneo doctor` to show later
+pub fn with_add_log(script: &str, log: &Path) -> String {
+    // Inside fish single quotes only `\` and `'` need escaping
+    let quoted = log
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'");
+    script.replace(
+        FISH_ADD_HOOK,
+        &format!(r#"command xneo add "$PWD" 2>>'{}' &"#, quoted),
+    )
+}
+
+/// `script` with `post_jump_command` (if any) run after every successful jump
+pub fn with_post_jump(script: &str, command: Option<&str>) -> String {
+    match command.map(str::trim).filter(|command| !command.is_empty()) {
+        Some(command) => script.replace(POST_JUMP_MARKER, command),
+        None => script.to_string(),
+    }
+}
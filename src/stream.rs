@@ -0,0 +1,57 @@
+use anyhow::Result;
+use std::io::{BufRead, Write};
+
+use crate::db::{Database, QueryOptions};
+use crate::display;
+
+/// Answers `xneo query --stdin-query`: each input line is a query whose
+/// keywords are separated by whitespace. Its results are written one per line
+/// through `format`, followed by an empty line marking the end of the block
+/// (results are never empty). Returns the number of queries answered.
+pub fn serve_queries(
+    db: &Database,
+    input: impl BufRead,
+    mut output: impl Write,
+    options: &QueryOptions,
+    format: &str,
+) -> Result<usize> {
+    let mut answered = 0;
+    for line in input.lines() {
+        let keywords: Vec<String> = line?.split_whitespace().map(str::to_string).collect();
+        for entry in db.query_with(&keywords, options)? {
+            writeln!(output, "{}", display::format_entry(format, &entry))?;
+        }
+        writeln!(output)?;
+        // The reader is waiting on this block before sending the next query
+        output.flush()?;
+        answered += 1;
+    }
+    Ok(answered)
+}
+
+/// Answers `xneo query --keywords-from-file`: one `query<TAB>result` line per
+/// non-blank input line, where `result` is the best match through `format`
+/// (empty when nothing matches). Returns the number of queries answered.
+pub fn resolve_queries(
+    db: &Database,
+    input: impl BufRead,
+    mut output: impl Write,
+    options: &QueryOptions,
+    format: &str,
+) -> Result<usize> {
+    let mut answered = 0;
+    for line in input.lines() {
+        let line = line?;
+        let query = line.trim();
+        if query.is_empty() {
+            continue;
+        }
+
+        let keywords: Vec<String> = query.split_whitespace().map(str::to_string).collect();
+        let best = db.query_with(&keywords, options)?.into_iter().next();
+        let result = best.map_or_else(String::new, |entry| display::format_entry(format, &entry));
+        writeln!(output, "{}\t{}", query, result)?;
+        answered += 1;
+    }
+    Ok(answered)
+}
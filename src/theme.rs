@@ -0,0 +1,162 @@
+use colored::{ColoredString, Colorize};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Output color scheme, set with the `theme` config option
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Light if `COLORFGBG` reports a light background, dark otherwise
+    Auto,
+    /// Bright colors for dark backgrounds
+    Dark,
+    /// Darker colors that stay readable on light backgrounds
+    Light,
+    /// No colors at all
+    None,
+}
+
+/// What a piece of output means; each theme picks a color per role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Heading,
+    Section,
+    Path,
+    Value,
+    Name,
+    Success,
+    Warning,
+    Error,
+    /// Something about to be removed or missing
+    Alert,
+    Info,
+    Muted,
+}
+
+impl Theme {
+    /// Resolves `Auto` to `Light` or `Dark`; other themes are returned as-is
+    pub fn resolve(self) -> Theme {
+        if self != Theme::Auto {
+            return self;
+        }
+
+        // `COLORFGBG` is "<fg>;<bg>" (sometimes "<fg>;<default>;<bg>");
+        // backgrounds 7 and 15 are white
+        let light = std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| value.rsplit(';').next().map(str::to_string))
+            .is_some_and(|bg| bg == "7" || bg == "15");
+        if light {
+            Theme::Light
+        } else {
+            Theme::Dark
+        }
+    }
+
+    pub fn paint(self, text: &str, role: Role) -> ColoredString {
+        match (self.resolve(), role) {
+            (Theme::None, _) => text.normal(),
+
+            (Theme::Light, Role::Heading) => text.green().bold(),
+            (Theme::Light, Role::Section) => text.magenta().bold(),
+            (Theme::Light, Role::Path) => text.blue(),
+            (Theme::Light, Role::Value) => text.bold(),
+            (Theme::Light, Role::Name) => text.magenta(),
+            (Theme::Light, Role::Success) => text.green().bold(),
+            (Theme::Light, Role::Warning) => text.red(),
+            (Theme::Light, Role::Error) => text.red().bold(),
+            (Theme::Light, Role::Alert) => text.red(),
+            (Theme::Light, Role::Info) => text.blue().bold(),
+            (Theme::Light, Role::Muted) => text.dimmed(),
+
+            (_, Role::Heading) => text.bright_green().bold(),
+            (_, Role::Section) => text.bright_yellow().bold(),
+            (_, Role::Path) => text.bright_blue(),
+            (_, Role::Value) => text.bright_cyan(),
+            (_, Role::Name) => text.bright_yellow(),
+            (_, Role::Success) => text.green().bold(),
+            (_, Role::Warning) => text.yellow().bold(),
+            (_, Role::Error) => text.red().bold(),
+            (_, Role::Alert) => text.bright_red(),
+            (_, Role::Info) => text.blue().bold(),
+            (_, Role::Muted) => text.dimmed(),
+        }
+    }
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+/// Selects the theme used by the helpers below for the rest of the process
+pub fn set(theme: Theme) {
+    let theme = theme.resolve();
+    if theme == Theme::None {
+        // Also covers anything painted without going through a role
+        colored::control::set_override(false);
+    }
+    CURRENT.store(theme as u8, Ordering::Relaxed);
+}
+
+fn current() -> Theme {
+    match CURRENT.load(Ordering::Relaxed) {
+        2 => Theme::Light,
+        3 => Theme::None,
+        _ => Theme::Dark,
+    }
+}
+
+pub fn paint(text: impl AsRef<str>, role: Role) -> ColoredString {
+    current().paint(text.as_ref(), role)
+}
+
+pub fn heading(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Heading)
+}
+
+pub fn section(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Section)
+}
+
+pub fn path(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Path)
+}
+
+pub fn value(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Value)
+}
+
+pub fn name(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Name)
+}
+
+pub fn success(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Success)
+}
+
+pub fn warning(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Warning)
+}
+
+pub fn error(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Error)
+}
+
+pub fn alert(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Alert)
+}
+
+pub fn info(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Info)
+}
+
+pub fn muted(text: impl AsRef<str>) -> ColoredString {
+    paint(text, Role::Muted)
+}
+
+/// "enabled"/"disabled" for a config flag
+pub fn toggle(enabled: bool) -> ColoredString {
+    if enabled {
+        paint("enabled", Role::Success)
+    } else {
+        paint("disabled", Role::Error)
+    }
+}